@@ -0,0 +1,291 @@
+use bevy::{ecs::world::EntityMut, prelude::*, reflect::TypeRegistry, tasks::TaskPool};
+use bevy_snap::*;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+macro_rules! components {
+    ($register:ident: $($name:ident),*) => {
+        $(
+            #[derive(Component, Reflect, Default, Clone)]
+            #[reflect(Component)]
+            struct $name(f32);
+        )*
+
+        fn $register(registry: &mut TypeRegistry) {
+            $(registry.write().register::<$name>();)*
+        }
+    };
+    ($register:ident, $insert:ident: $($name:ident),*) => {
+        components!($register: $($name),*);
+
+        /// Inserts the `index`th of the components, wrapping around.
+        fn $insert(entity: &mut EntityMut, index: usize) {
+            let inserts: &[fn(&mut EntityMut)] = &[$(|entity| {
+                entity.insert($name(0.));
+            }),*];
+            inserts[index % inserts.len()](entity);
+        }
+    };
+}
+
+components!(register_components: A, B, C, D, E, F, G, H);
+
+// registered, but rare or never spawned, like most types in a real game's registry
+components!(
+    register_unused, insert_unused: U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14,
+    U15, U16, U17, U18, U19, U20, U21, U22, U23, U24, U25, U26, U27, U28, U29, U30, U31, U32, U33,
+    U34, U35, U36, U37, U38, U39, U40, U41, U42, U43, U44, U45, U46, U47
+);
+
+#[derive(Default)]
+struct BenchSnap;
+
+impl SnapType for BenchSnap {
+    fn add_types(registry: &mut TypeRegistry) {
+        registry.write().register::<Transform>();
+        register_components(registry);
+    }
+}
+
+/// Same components as `BenchSnap`, but with a large type registry.
+#[derive(Default)]
+struct WideSnap;
+
+impl SnapType for WideSnap {
+    fn add_types(registry: &mut TypeRegistry) {
+        BenchSnap::add_types(registry);
+        register_unused(registry);
+    }
+}
+
+/// Same components as `BenchSnap`, but stored with the typed fast path.
+#[derive(Default)]
+struct CloneSnap;
+
+impl SnapType for CloneSnap {
+    fn add_types(registry: &mut TypeRegistry) {
+        let mut registry = registry.write();
+        register_snap_clone::<Transform>(&mut registry);
+        register_snap_clone::<A>(&mut registry);
+        register_snap_clone::<B>(&mut registry);
+        register_snap_clone::<C>(&mut registry);
+        register_snap_clone::<D>(&mut registry);
+        register_snap_clone::<E>(&mut registry);
+        register_snap_clone::<F>(&mut registry);
+        register_snap_clone::<G>(&mut registry);
+        register_snap_clone::<H>(&mut registry);
+    }
+}
+
+const ENTITY_COUNTS: [u32; 2] = [1_000, 10_000];
+
+fn new_app() -> App {
+    let mut app = App::new();
+    app.add_plugin(SnapPlugin::<BenchSnap>::default())
+        .add_plugin(SnapPlugin::<WideSnap>::default())
+        .add_plugin(SnapPlugin::<CloneSnap>::default());
+    app
+}
+
+/// Spawns `count` tracked entities, spread over a few archetypes.
+fn populate(world: &mut World, count: u32) {
+    for i in 0..count {
+        let mut entity = world.spawn();
+        entity
+            .insert(SnapshotId::<BenchSnap>::new(i))
+            .insert(SnapshotId::<WideSnap>::new(i))
+            .insert(SnapshotId::<CloneSnap>::new(i))
+            .insert(Transform::from_xyz(i as f32, 0., 0.))
+            .insert_bundle((A(1.), B(2.), C(3.)));
+        match i % 4 {
+            0 => entity.insert_bundle((D(4.), E(5.))),
+            1 => entity.insert_bundle((F(6.), G(7.))),
+            2 => entity.insert(H(8.)),
+            _ => &mut entity,
+        };
+    }
+}
+
+/// Spawns `count` entities with registered components, but no snapshot id.
+fn populate_untagged(world: &mut World, count: u32) {
+    for i in 0..count {
+        world
+            .spawn()
+            .insert(Transform::from_xyz(i as f32, 0., 0.))
+            .insert_bundle((A(1.), B(2.)));
+    }
+}
+
+/// Gives each tracked entity one of the rarely used components.
+fn populate_wide(world: &mut World) {
+    let entities = world
+        .query_filtered::<Entity, With<SnapshotId<WideSnap>>>()
+        .iter(world)
+        .collect::<Vec<_>>();
+    for (i, entity) in entities.into_iter().enumerate() {
+        insert_unused(&mut world.entity_mut(entity), i);
+    }
+}
+
+fn save(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save");
+    for count in ENTITY_COUNTS {
+        let mut app = new_app();
+        populate(&mut app.world, count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| app.world.save_snapshot::<BenchSnap>().unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn save_parallel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save_parallel");
+    let task_pool = TaskPool::new();
+    let mut type_registry = TypeRegistry::default();
+    BenchSnap::add_types(&mut type_registry);
+    for count in ENTITY_COUNTS {
+        let mut app = new_app();
+        populate(&mut app.world, count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| {
+                WorldSnapshot::<BenchSnap>::from_world_parallel(
+                    &app.world,
+                    &type_registry,
+                    &task_pool,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+fn save_mostly_untagged(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save_mostly_untagged");
+    for count in ENTITY_COUNTS {
+        let mut app = new_app();
+        populate(&mut app.world, count / 10);
+        populate_untagged(&mut app.world, count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| app.world.save_snapshot::<BenchSnap>().unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn load_existing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_existing");
+    for count in ENTITY_COUNTS {
+        let mut app = new_app();
+        populate(&mut app.world, count);
+        let snapshot = app.world.save_snapshot::<BenchSnap>().unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| app.world.load_snapshot(&snapshot).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn load_empty(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_empty");
+    group.sample_size(10);
+    for count in ENTITY_COUNTS {
+        let mut app = new_app();
+        populate(&mut app.world, count);
+        let snapshot = app.world.save_snapshot::<BenchSnap>().unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter_batched_ref(
+                new_app,
+                |app| app.world.load_snapshot(&snapshot).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn load_despawning(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_despawning");
+    group.sample_size(10);
+    let snapshot = new_app().world.save_snapshot::<BenchSnap>().unwrap();
+    for count in ENTITY_COUNTS {
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter_batched_ref(
+                || {
+                    let mut app = new_app();
+                    populate(&mut app.world, count);
+                    app
+                },
+                |app| app.world.load_snapshot(&snapshot).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn save_clone(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save_clone");
+    for count in ENTITY_COUNTS {
+        let mut app = new_app();
+        populate(&mut app.world, count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| app.world.save_snapshot::<CloneSnap>().unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn load_existing_clone(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_existing_clone");
+    for count in ENTITY_COUNTS {
+        let mut app = new_app();
+        populate(&mut app.world, count);
+        let snapshot = app.world.save_snapshot::<CloneSnap>().unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| app.world.load_snapshot(&snapshot).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn load_wide_registry(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_wide_registry");
+    for count in ENTITY_COUNTS {
+        let mut app = new_app();
+        populate(&mut app.world, count);
+        let snapshot = app.world.save_snapshot::<WideSnap>().unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| app.world.load_snapshot(&snapshot).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn load_wide_registry_populated(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_wide_registry_populated");
+    for count in ENTITY_COUNTS {
+        let mut app = new_app();
+        populate(&mut app.world, count);
+        populate_wide(&mut app.world);
+        let snapshot = app.world.save_snapshot::<WideSnap>().unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| app.world.load_snapshot(&snapshot).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    save,
+    save_parallel,
+    save_mostly_untagged,
+    load_existing,
+    load_empty,
+    load_despawning,
+    load_wide_registry,
+    load_wide_registry_populated,
+    save_clone,
+    load_existing_clone
+);
+criterion_main!(benches);
@@ -0,0 +1,143 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    DeriveInput, Ident, Path, Token, Type,
+};
+
+/// Implements `SnapType`, registering the listed components, resources, states and events:
+///
+/// ```ignore
+/// #[derive(Default, SnapType)]
+/// #[snap(components(Transform, Velocity), resources(Score))]
+/// #[snap(states(GameState), events(ScoreEvent))]
+/// struct MySnap;
+/// ```
+///
+/// Hooks are added with `#[snap(hooks = path::to_fn)]`, naming a `fn(&mut SnapHooks)` that is
+/// called from `add_hooks`.
+///
+/// The `ReflectComponent` and `ReflectResource` type data is added by the macro, so it doesn't
+/// matter whether the types have `#[reflect(Component)]` or `#[reflect(Resource)]`. Components
+/// that can't be saved that way are compile errors.
+///
+/// Resources are created with `FromWorld` (or `Default`) when they are loaded, and with
+/// `FromReflect` if they have no `FromWorld`. Resources with neither need `ReflectDefault` type
+/// data, or saving and loading fails with `SnapError::MissingConstructor`.
+#[proc_macro_derive(SnapType, attributes(snap))]
+pub fn derive_snap_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let mut types = SnapTypes::default();
+    for attr in input.attrs.iter().filter(|attr| attr.path.is_ident("snap")) {
+        match attr.parse_args::<SnapTypes>() {
+            Ok(attr_types) => types.extend(attr_types),
+            Err(error) => return error.to_compile_error().into(),
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let components = types.components;
+    let resources = types.resources;
+    let states = types.states;
+    let events = types.events;
+    let hooks = types.hooks;
+
+    quote! {
+        impl #impl_generics ::bevy_snap::SnapType for #name #type_generics #where_clause {
+            fn add_types(registry: &mut ::bevy_snap::__macro::TypeRegistry) {
+                let mut registry = registry.write();
+                #(
+                    ::bevy_snap::__macro::register_component::<#components>(&mut registry);
+                )*
+                #(
+                    {
+                        use ::bevy_snap::__macro::{
+                            ResourceConstructor, ViaFromReflect, ViaFromWorld, ViaReflectDefault,
+                        };
+                        let reflect_resource =
+                            (&&&ResourceConstructor::<#resources>::default()).reflect_resource();
+                        ::bevy_snap::__macro::register_resource::<#resources>(
+                            &mut registry,
+                            reflect_resource,
+                        );
+                    }
+                )*
+            }
+
+            fn add_hooks(hooks: &mut ::bevy_snap::SnapHooks) {
+                #(
+                    #hooks(hooks);
+                )*
+            }
+
+            fn add_flow(flow: &mut ::bevy_snap::SnapFlow) {
+                #(
+                    flow.state::<#states>();
+                )*
+                #(
+                    flow.events::<#events>();
+                )*
+            }
+        }
+    }
+    .into()
+}
+
+#[derive(Default)]
+struct SnapTypes {
+    components: Vec<Type>,
+    resources: Vec<Type>,
+    states: Vec<Type>,
+    events: Vec<Type>,
+    hooks: Vec<Path>,
+}
+
+impl SnapTypes {
+    fn extend(&mut self, other: SnapTypes) {
+        self.components.extend(other.components);
+        self.resources.extend(other.resources);
+        self.states.extend(other.states);
+        self.events.extend(other.events);
+        self.hooks.extend(other.hooks);
+    }
+}
+
+impl Parse for SnapTypes {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut types = SnapTypes::default();
+        while !input.is_empty() {
+            let kind: Ident = input.parse()?;
+            if kind == "hooks" {
+                input.parse::<Token![=]>()?;
+                types.hooks.push(input.parse()?);
+                if !input.is_empty() {
+                    input.parse::<Token![,]>()?;
+                }
+                continue;
+            }
+            let content;
+            parenthesized!(content in input);
+            let list = Punctuated::<Type, Token![,]>::parse_terminated(&content)?;
+            match kind.to_string().as_str() {
+                "components" => types.components.extend(list),
+                "resources" => types.resources.extend(list),
+                "states" => types.states.extend(list),
+                "events" => types.events.extend(list),
+                _ => return Err(syn::Error::new(
+                    kind.span(),
+                    "expected `components(...)`, `resources(...)`, `states(...)`, `events(...)` \
+                     or `hooks = ...`",
+                )),
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(types)
+    }
+}
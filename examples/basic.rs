@@ -25,10 +25,10 @@ impl SnapType for MySnap {
 #[reflect(Component)]
 struct Player;
 
-// Resources also need to implement the Reflect and Component traits trait
-#[derive(Component, Reflect, Default)]
-// Resources also (at least at the moment) need to be marked as Components, as well as Resources
-#[reflect(Component, Resource)]
+// Resources also need to implement the Reflect trait
+#[derive(Reflect, Default)]
+// and be marked as Resources
+#[reflect(Resource)]
 struct Steps(f32);
 
 // Actual save data is contained in the WorldSnapshot type,
@@ -0,0 +1,94 @@
+use std::{any::TypeId, marker::PhantomData};
+
+use bevy::{
+    ecs::query::{FilterFetch, WorldQuery},
+    prelude::*,
+};
+
+use crate::{hierarchy, SnapRegistry, SnapType, SnapshotId, SnapshotIdProvider};
+
+/// Finds the untagged entities one auto tag filter wants tagged.
+pub(crate) type AutoTagFilter = Box<dyn FnMut(&mut World) -> Vec<Entity> + Send + Sync>;
+
+/// The filters added with `SnapPlugin::auto_tag` and `SnapPlugin::auto_tag_registered`.
+pub(crate) struct AutoTagFilters<T: SnapType> {
+    pub filters: Vec<AutoTagFilter>,
+    pub t: PhantomData<T>,
+}
+
+/// Untagged entities matching the query filter `F`.
+pub(crate) fn filtered<T, F>() -> AutoTagFilter
+where
+    T: SnapType,
+    F: WorldQuery + 'static,
+    F::Fetch: FilterFetch,
+{
+    let mut query = None::<QueryState<Entity, (Without<SnapshotId<T>>, F)>>;
+    Box::new(move |world| {
+        query
+            .get_or_insert_with(|| world.query_filtered())
+            .iter(world)
+            .collect()
+    })
+}
+
+/// Untagged entities that have at least one component registered in the `SnapType`.
+pub(crate) fn registered<T: SnapType>() -> AutoTagFilter {
+    Box::new(|world| {
+        let registry = world
+            .get_resource::<SnapRegistry<T>>()
+            .expect("No type registry found, did you forget to initialize the save plugin?");
+        let registered_component_ids = registry
+            .type_registry
+            .read()
+            .iter()
+            .filter(|registration| registration.data::<ReflectComponent>().is_some())
+            .filter(|registration| !hierarchy::is_hierarchy_type(registration.type_id()))
+            .filter_map(|registration| world.components().get_id(registration.type_id()))
+            .collect::<Vec<_>>();
+        let snapshot_id_component = world.components().get_id(TypeId::of::<SnapshotId<T>>());
+
+        world
+            .archetypes()
+            .iter()
+            .filter(|archetype| {
+                !matches!(snapshot_id_component, Some(component_id) if archetype.contains(component_id))
+            })
+            .filter(|archetype| {
+                registered_component_ids
+                    .iter()
+                    .any(|component_id| archetype.contains(*component_id))
+            })
+            .flat_map(|archetype| archetype.entities().iter().copied())
+            .collect()
+    })
+}
+
+/// Tags the entities found by all auto tag filters, in one go.
+///
+/// Entities are tagged in entity order, so peers that spawn the same entities assign the same ids,
+/// regardless of archetype and query iteration order, and of which filters matched them. Entities
+/// matching several filters are tagged once.
+pub(crate) fn auto_tag<T: SnapType>(world: &mut World) {
+    let mut entities = world.resource_scope(|world, mut filters: Mut<AutoTagFilters<T>>| {
+        filters
+            .filters
+            .iter_mut()
+            .flat_map(|filter| filter(world))
+            .collect::<Vec<_>>()
+    });
+    if entities.is_empty() {
+        return;
+    }
+    entities.sort_by_key(|entity| (entity.id(), entity.generation()));
+    entities.dedup();
+
+    let mut id_provider = world.resource_mut::<SnapshotIdProvider<T>>();
+    let ids = entities
+        .iter()
+        .map(|_| id_provider.next())
+        .collect::<Vec<_>>();
+    for (entity, id) in entities.into_iter().zip(ids) {
+        world.entity_mut(entity).insert(id);
+    }
+}
@@ -0,0 +1,126 @@
+use std::hash::Hash;
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    hierarchy, hooks,
+    world_snapshot::{check_handle_paths, check_type_data},
+    LoadFilter, LoadMode, LoadReport, SnapError, SnapRegistry, SnapType, SnapWorldExt, SnapshotId,
+    WorldSnapshot,
+};
+
+type ChunkKey<K> = Box<dyn Fn(&World, Entity) -> Option<K> + Send + Sync>;
+
+/// Region-scoped snapshots for streaming worlds.
+///
+/// Tagged entities are assigned to chunks by a key function, e.g. by grid cell of their
+/// `Transform`. Unloading a chunk saves its entities and despawns them, loading it restores them.
+///
+/// Snapshot ids of unloaded entities are never released, so the `SnapshotIdProvider` keeps ids
+/// unique across chunks. A chunk's snapshot is removed when it is loaded, so each id is either in
+/// the world or in one stored chunk.
+pub struct SnapChunks<T: SnapType, K: Eq + Hash> {
+    key: ChunkKey<K>,
+    chunks: HashMap<K, WorldSnapshot<T>>,
+}
+
+impl<T: SnapType, K: Eq + Hash> SnapChunks<T, K> {
+    /// Uses `key` to find the chunk of a tagged entity. Entities it returns `None` for are never
+    /// saved with a chunk.
+    pub fn new(key: impl Fn(&World, Entity) -> Option<K> + Send + Sync + 'static) -> Self {
+        Self {
+            key: Box::new(key),
+            chunks: default(),
+        }
+    }
+
+    /// The chunk `entity` belongs to.
+    pub fn chunk_of(&self, world: &World, entity: Entity) -> Option<K> {
+        (self.key)(world, entity)
+    }
+
+    /// Takes a snapshot of the tagged entities in `chunk`, and stores it, replacing any earlier
+    /// snapshot of the chunk. Resources are not included.
+    ///
+    /// Per-component pre-save hooks only run for the chunk's entities. Global and per-resource
+    /// pre-save hooks run like they do for a full save.
+    pub fn save(&mut self, world: &mut World, chunk: K) -> Result<&WorldSnapshot<T>, SnapError> {
+        let type_registry = world
+            .get_resource::<SnapRegistry<T>>()
+            .ok_or(SnapError::MissingRegistry(std::any::type_name::<T>()))?
+            .type_registry
+            .clone();
+        check_type_data(&type_registry.read())?;
+        hooks::run_pre_save::<T>(world, |world, entity| {
+            self.chunk_of(world, entity).as_ref() == Some(&chunk)
+        });
+        let in_chunk = |entity| self.chunk_of(world, entity).as_ref() == Some(&chunk);
+        check_handle_paths::<T>(world, &type_registry.read(), in_chunk)?;
+        let snapshot = WorldSnapshot::from_entities(world, &type_registry, in_chunk);
+        let stored = self.chunks.entry(chunk).or_default();
+        *stored = snapshot;
+        Ok(stored)
+    }
+
+    /// Saves `chunk`, then despawns its entities, along with their untracked children.
+    ///
+    /// Hierarchy links between entities of different chunks are not saved: tracked children of
+    /// the chunk's entities stay in the world, detached, and the chunk's entities are detached from
+    /// parents outside of it.
+    pub fn unload(&mut self, world: &mut World, chunk: K) -> Result<(), SnapError> {
+        let entities = self
+            .save(world, chunk)?
+            .entities
+            .iter()
+            .map(|snapshot_entity| snapshot_entity.entity)
+            .collect::<Vec<_>>();
+        let unloaded = entities.iter().copied().collect::<HashSet<_>>();
+        let kept = world
+            .query_filtered::<Entity, With<SnapshotId<T>>>()
+            .iter(world)
+            .filter(|entity| !unloaded.contains(entity))
+            .collect::<HashSet<_>>();
+        hierarchy::despawn_batch(world, &entities, &kept);
+        Ok(())
+    }
+
+    /// Restores the stored snapshot of `chunk`, if there is one, and removes it from the store.
+    ///
+    /// Only the chunk's entities are touched. Entities that are already in the world are updated.
+    pub fn load(&mut self, world: &mut World, chunk: &K) -> Result<Option<LoadReport>, SnapError> {
+        let snapshot = match self.chunks.get(chunk) {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        };
+        let filter = snapshot.entities.iter().fold(
+            LoadFilter::none().with_all_components(),
+            |filter, snapshot_entity| filter.with_entity(snapshot_entity.snapshot_id.clone()),
+        );
+        let report = world.load_snapshot_with(snapshot, LoadMode::Replace, &filter)?;
+        self.chunks.remove(chunk);
+        Ok(Some(report))
+    }
+
+    /// The stored snapshot of `chunk`, e.g. to write it to disk.
+    pub fn get(&self, chunk: &K) -> Option<&WorldSnapshot<T>> {
+        self.chunks.get(chunk)
+    }
+
+    /// Stores a snapshot of `chunk`, e.g. one read from disk, to be restored by `load`.
+    pub fn insert(&mut self, chunk: K, snapshot: WorldSnapshot<T>) {
+        self.chunks.insert(chunk, snapshot);
+    }
+
+    /// Removes the stored snapshot of `chunk`, without loading it.
+    pub fn remove(&mut self, chunk: &K) -> Option<WorldSnapshot<T>> {
+        self.chunks.remove(chunk)
+    }
+
+    /// All stored chunks.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &WorldSnapshot<T>)> {
+        self.chunks.iter()
+    }
+}
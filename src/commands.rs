@@ -13,11 +13,36 @@ pub struct SaveCommand<T: SnapType> {
 #[derive(Default)]
 pub struct LoadCommand<T: SnapType> {
     snapshot: WorldSnapshot<T>,
+    mode: LoadMode,
+    filter: LoadFilter,
+}
+
+impl<T: SnapType> LoadCommand<T> {
+    pub fn new(snapshot: WorldSnapshot<T>) -> Self {
+        Self {
+            snapshot,
+            mode: default(),
+            filter: default(),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: LoadMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Only applies the parts of the snapshot selected by `filter`.
+    pub fn with_filter(mut self, filter: LoadFilter) -> Self {
+        self.filter = filter;
+        self
+    }
 }
 
 pub trait SaveCommandExt {
     fn save<T: SnapType>(&mut self);
     fn load<T: SnapType>(&mut self, snapshot: WorldSnapshot<T>);
+    fn load_with_mode<T: SnapType>(&mut self, snapshot: WorldSnapshot<T>, mode: LoadMode);
+    fn load_filtered<T: SnapType>(&mut self, snapshot: WorldSnapshot<T>, filter: LoadFilter);
 }
 
 impl SaveCommandExt for Commands<'_, '_> {
@@ -26,32 +51,48 @@ impl SaveCommandExt for Commands<'_, '_> {
     }
 
     fn load<T: SnapType>(&mut self, snapshot: WorldSnapshot<T>) {
-        self.add(LoadCommand { snapshot })
+        self.add(LoadCommand::new(snapshot))
+    }
+
+    fn load_with_mode<T: SnapType>(&mut self, snapshot: WorldSnapshot<T>, mode: LoadMode) {
+        self.add(LoadCommand::new(snapshot).with_mode(mode))
+    }
+
+    fn load_filtered<T: SnapType>(&mut self, snapshot: WorldSnapshot<T>, filter: LoadFilter) {
+        self.add(LoadCommand::new(snapshot).with_filter(filter))
     }
 }
 
 impl<T: SnapType> Command for SaveCommand<T> {
     fn write(self, world: &mut World) {
-        let registry = world
-            .get_resource::<SnapRegistry<T>>()
-            .expect("No type registry found, did you forget to initialize the save plugin?");
-
-        let snapshot = WorldSnapshot::from_world(world, &registry.type_registry);
-        let mut save_events = world.get_resource_mut::<Events<SaveEvent<T>>>().unwrap();
-        save_events.send(snapshot.into());
+        match world.save_snapshot::<T>() {
+            Ok(snapshot) => {
+                let mut save_events = world.get_resource_mut::<Events<SaveEvent<T>>>().unwrap();
+                save_events.send(snapshot.into());
+            }
+            Err(error) => send_error::<T>(world, error),
+        }
     }
 }
 
 impl<T: SnapType> Command for LoadCommand<T> {
     fn write(self, world: &mut World) {
-        let registry = world
-            .get_resource::<SnapRegistry<T>>()
-            .expect("No type registry found, did you forget to initialize the save plugin?")
-            .type_registry
-            .clone();
+        info!("restoring save");
 
-        info!("restoring save {:?}", registry);
+        match world.load_snapshot_with(&self.snapshot, self.mode, &self.filter) {
+            Ok(report) => {
+                if let Some(mut load_events) = world.get_resource_mut::<Events<LoadEvent<T>>>() {
+                    load_events.send(report.into());
+                }
+            }
+            Err(error) => send_error::<T>(world, error),
+        }
+    }
+}
 
-        self.snapshot.write_to_world(world, registry);
+fn send_error<T: SnapType>(world: &mut World, error: SnapError) {
+    error!("{}", error);
+    if let Some(mut error_events) = world.get_resource_mut::<Events<SnapErrorEvent<T>>>() {
+        error_events.send(error.into());
     }
 }
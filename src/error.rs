@@ -0,0 +1,108 @@
+use std::{fmt::Display, marker::PhantomData};
+
+use bevy::prelude::*;
+
+use crate::{SnapType, SnapshotIssue, SnapshotKey};
+
+/// Reasons saving or loading a snapshot can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SnapError {
+    /// There is no type registry for the `SnapType`, i.e. the `SnapPlugin` was not added.
+    MissingRegistry(&'static str),
+    /// A value in the snapshot is not of the type it is applied to.
+    TypeMismatch { expected: String, found: String },
+    /// A registered type is neither `#[reflect(Component)]` nor `#[reflect(Resource)]`.
+    MissingTypeData(String),
+    /// There is no type with this name in the app's `TypeRegistry`.
+    UnknownType(String),
+    /// A type lists a field in `SnapTransientFields::TRANSIENT_FIELDS` that it doesn't have.
+    UnknownTransientField { type_name: String, field: String },
+    /// A resource that was expected to be in the world is missing.
+    MissingResource(String),
+    /// A registered resource can't be created when it's missing from the world, as it has neither
+    /// `FromWorld`, `FromReflect` nor `ReflectDefault` type data.
+    MissingConstructor(String),
+    /// A tracked handle points to an asset that was not loaded from a path, so it can't be saved.
+    MissingAssetPath { handle: String, entity: Entity },
+    /// Several tagged entities in the world share the same snapshot id.
+    DuplicateId(SnapshotKey),
+    /// The `SnapshotIdProvider` has run out of ids for the entities of an additive load.
+    OutOfIds,
+    /// The snapshot has duplicate ids or an inconsistent hierarchy, see `WorldSnapshot::validate`.
+    InvalidSnapshot(Vec<SnapshotIssue>),
+}
+
+impl Display for SnapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapError::MissingRegistry(snap_type) => write!(
+                f,
+                "no type registry found for {}, did you forget to add the SnapPlugin?",
+                snap_type
+            ),
+            SnapError::TypeMismatch { expected, found } => {
+                write!(f, "expected a value of type {}, found {}", expected, found)
+            }
+            SnapError::MissingTypeData(name) => write!(
+                f,
+                "{} is registered, but is neither a component nor a resource. Did you forget to add #[reflect(Component)] or #[reflect(Resource)] to it? Resources without Default or FromWorld can be registered with register_resource_from_reflect",
+                name
+            ),
+            SnapError::UnknownType(name) => write!(
+                f,
+                "no type named {} is registered in the app's TypeRegistry",
+                name
+            ),
+            SnapError::UnknownTransientField { type_name, field } => write!(
+                f,
+                "{} lists {} as a transient field, but has no such field",
+                type_name, field
+            ),
+            SnapError::MissingResource(name) => write!(f, "resource {} does not exist", name),
+            SnapError::MissingConstructor(name) => write!(
+                f,
+                "resource {} can't be created when loading, it needs Default, FromWorld or FromReflect, or #[reflect(Default)]",
+                name
+            ),
+            SnapError::MissingAssetPath { handle, entity } => write!(
+                f,
+                "the {} of entity {:?} has no asset path, only handles of assets loaded through the AssetServer can be saved",
+                handle, entity
+            ),
+            SnapError::DuplicateId(id) => {
+                write!(f, "several entities share the snapshot id {:?}", id)
+            }
+            SnapError::OutOfIds => write!(
+                f,
+                "the SnapshotIdProvider has run out of ids, release unused ids to load additively"
+            ),
+            SnapError::InvalidSnapshot(issues) => {
+                write!(f, "invalid snapshot: ")?;
+                for (i, issue) in issues.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", issue)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapError {}
+
+/// Sent when a `SaveCommand` or `LoadCommand` fails.
+pub struct SnapErrorEvent<T: SnapType> {
+    pub error: SnapError,
+    t: PhantomData<T>,
+}
+
+impl<T: SnapType> From<SnapError> for SnapErrorEvent<T> {
+    fn from(error: SnapError) -> Self {
+        Self {
+            error,
+            t: default(),
+        }
+    }
+}
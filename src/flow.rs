@@ -0,0 +1,172 @@
+use std::{
+    any::{Any, TypeId},
+    hash::{BuildHasher, Hash, Hasher},
+};
+
+use bevy::{
+    ecs::{
+        event::Events,
+        schedule::{StateData, SystemStage},
+        system::Resource,
+    },
+    prelude::*,
+    utils::FixedState,
+};
+
+/// Game flow that can't be saved through reflection: `State`s and `Events`. Add them in
+/// `SnapType::add_flow`.
+///
+/// They are saved and loaded like resources, so `LoadFilter::with_resource::<State<S>>()` selects
+/// them, and `LoadMode::Additive` leaves them untouched.
+#[derive(Clone, Default)]
+pub struct SnapFlow {
+    pub(crate) types: Vec<FlowType>,
+}
+
+impl SnapFlow {
+    /// Saves the stack of `State<S>`.
+    ///
+    /// Loading restores the stack as it was, without running any enter, exit, pause or resume
+    /// systems. Queued state changes are dropped.
+    pub fn state<S: StateData>(&mut self) -> &mut Self {
+        self.types.push(FlowType {
+            type_id: TypeId::of::<State<S>>(),
+            type_name: std::any::type_name::<State<S>>(),
+            capture: |world| {
+                let state = world.get_resource::<State<S>>()?;
+                let stack = state
+                    .inactives()
+                    .iter()
+                    .chain([state.current()])
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let mut hasher = FixedState.build_hasher();
+                stack.hash(&mut hasher);
+                Some((Box::new(stack), Some(hasher.finish())))
+            },
+            restore: |world, value| {
+                let stack = value.as_any().downcast_ref::<Vec<S>>().unwrap();
+                let inserted = !world.contains_resource::<State<S>>();
+                world.insert_resource(settled_state(stack));
+                inserted
+            },
+            remove: |world| world.remove_resource::<State<S>>().is_some(),
+        });
+        self
+    }
+
+    /// Saves the events in both buffers of `Events<E>`.
+    ///
+    /// Loading replaces the buffered events, which are sent again, so `EventReader`s see them as
+    /// new events.
+    pub fn events<E: Resource + Clone>(&mut self) -> &mut Self {
+        self.types.push(FlowType {
+            type_id: TypeId::of::<Events<E>>(),
+            type_name: std::any::type_name::<Events<E>>(),
+            capture: |world| {
+                let events = world.get_resource::<Events<E>>()?;
+                let current = events
+                    .iter_current_update_events()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let mut reader = events.get_reader();
+                let all = reader.iter(events).cloned().collect::<Vec<_>>();
+                let previous = all[..all.len() - current.len()].to_vec();
+                Some((Box::new(SavedEvents { previous, current }), None))
+            },
+            restore: |world, value| {
+                let saved = value.as_any().downcast_ref::<SavedEvents<E>>().unwrap();
+                let inserted = !world.contains_resource::<Events<E>>();
+                let mut events = world.get_resource_or_insert_with(Events::<E>::default);
+                events.clear();
+                events.extend(saved.previous.iter().cloned());
+                // the previous events are dropped one update before the current ones
+                events.update();
+                events.extend(saved.current.iter().cloned());
+                inserted
+            },
+            remove: |world| world.remove_resource::<Events<E>>().is_some(),
+        });
+        self
+    }
+}
+
+/// How to save and load one type of `SnapFlow`.
+#[derive(Clone)]
+pub(crate) struct FlowType {
+    pub type_id: TypeId,
+    pub type_name: &'static str,
+    /// The saved value and its hash, if the world has the resource.
+    pub capture: fn(&World) -> Option<CapturedFlow>,
+    /// Restores the resource, and returns whether it was inserted.
+    pub restore: fn(&mut World, &dyn FlowValue) -> bool,
+    /// Removes the resource, and returns whether there was one.
+    pub remove: fn(&mut World) -> bool,
+}
+
+/// A saved `SnapFlow` value, and its hash if it is part of the checksum.
+pub(crate) type CapturedFlow = (Box<dyn FlowValue>, Option<u64>);
+
+pub(crate) trait FlowValue: Send + Sync {
+    fn clone_value(&self) -> Box<dyn FlowValue>;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<V: Clone + Send + Sync + 'static> FlowValue for V {
+    fn clone_value(&self) -> Box<dyn FlowValue> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Clone)]
+struct SavedEvents<E> {
+    previous: Vec<E>,
+    current: Vec<E>,
+}
+
+/// A saved `SnapFlow` value, by the type name of its resource.
+pub(crate) struct FlowEntry {
+    pub type_name: &'static str,
+    pub value: Box<dyn FlowValue>,
+}
+
+impl Clone for FlowEntry {
+    fn clone(&self) -> Self {
+        Self {
+            type_name: self.type_name,
+            value: self.value.clone_value(),
+        }
+    }
+}
+
+impl std::fmt::Debug for FlowEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FlowEntry")
+            .field("type_name", &self.type_name)
+            .finish()
+    }
+}
+
+/// A `State` with the given stack, that is done transitioning.
+///
+/// `State` has no way to set its stack directly, so the transitions are run in a world of their
+/// own, where no systems depend on them.
+fn settled_state<S: StateData>(stack: &[S]) -> State<S> {
+    let mut world = World::new();
+    world.insert_resource(State::new(stack[0].clone()));
+    let mut stage =
+        SystemStage::single_threaded().with_system_set(State::<S>::get_driver().with_system(|| {}));
+    stage.run(&mut world);
+    for state in &stack[1..] {
+        let mut current = world.resource_mut::<State<S>>();
+        // consecutive duplicates can't be pushed, and can't be in a stack in the first place
+        if current.overwrite_push(state.clone()).is_ok() {
+            stage.run(&mut world);
+        }
+    }
+    world.remove_resource::<State<S>>().unwrap()
+}
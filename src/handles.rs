@@ -0,0 +1,260 @@
+use std::any::{Any, TypeId};
+
+use bevy::{
+    asset::{Asset, HandleId},
+    prelude::*,
+    reflect::{DynamicStruct, FromType, ReflectRef, TypeRegistryInternal},
+    utils::HashMap,
+};
+
+use crate::SnapError;
+
+/// Saves `Handle` components by the path of their asset, and reloads them through the
+/// `AssetServer`. Register it with `register_asset_handle`.
+///
+/// Handles are only valid while the app runs, so only handles of assets loaded from a path can be
+/// saved. Saving a handle to an asset that was created at runtime fails with
+/// `SnapError::MissingAssetPath`, unless the type was registered with `register_embedded_asset`.
+#[derive(Clone)]
+pub struct ReflectSnapHandle {
+    path: fn(&World, Entity) -> Result<String, SnapError>,
+    load: fn(&mut World, Entity, &str),
+    embed: Option<EmbedAsset>,
+}
+
+/// How assets without a path are embedded in snapshots, see `register_embedded_asset`.
+#[derive(Clone)]
+struct EmbedAsset {
+    id: fn(&World, Entity) -> HandleId,
+    capture: fn(&World, HandleId) -> Option<Box<dyn AssetValue>>,
+    add: fn(&mut World, &dyn AssetValue) -> Result<HandleUntyped, SnapError>,
+    insert: fn(&mut World, Entity, &HandleUntyped),
+}
+
+/// A handle as it is stored in a snapshot.
+pub(crate) enum SavedHandle<'a> {
+    Path(&'a str),
+    /// The id the handle had when it was saved, see `WorldSnapshot::assets`.
+    Embedded(HandleId),
+}
+
+impl ReflectSnapHandle {
+    /// Makes sure the handle of `entity` can be saved.
+    pub(crate) fn check(&self, world: &World, entity: Entity) -> Result<(), SnapError> {
+        match (self.path(world, entity), &self.embed) {
+            (Err(_), Some(embed))
+                if (embed.capture)(world, (embed.id)(world, entity)).is_some() =>
+            {
+                Ok(())
+            }
+            (result, _) => result.map(|_| ()),
+        }
+    }
+
+    fn path(&self, world: &World, entity: Entity) -> Result<String, SnapError> {
+        (self.path)(world, entity)
+    }
+
+    /// The handle of `entity` as it is stored in a snapshot, or `None` if it can't be saved.
+    pub(crate) fn save(
+        &self,
+        world: &World,
+        entity: Entity,
+        type_name: &str,
+    ) -> Option<DynamicStruct> {
+        let mut saved = DynamicStruct::default();
+        saved.set_name(type_name.to_string());
+        match (self.path(world, entity), &self.embed) {
+            (Ok(path), _) => saved.insert("path", path),
+            (Err(_), Some(embed)) => saved.insert("embedded", (embed.id)(world, entity)),
+            (Err(_), None) => return None,
+        }
+        Some(saved)
+    }
+
+    /// Clones the asset of an embedded handle, if the type supports embedding and the asset exists.
+    pub(crate) fn capture_asset(&self, world: &World, id: HandleId) -> Option<Box<dyn AssetValue>> {
+        self.embed
+            .as_ref()
+            .and_then(|embed| (embed.capture)(world, id))
+    }
+
+    /// Adds an embedded asset to the world, and returns a handle to it.
+    pub(crate) fn add_asset(
+        &self,
+        world: &mut World,
+        asset: &dyn AssetValue,
+    ) -> Option<Result<HandleUntyped, SnapError>> {
+        self.embed.as_ref().map(|embed| (embed.add)(world, asset))
+    }
+
+    /// Inserts the handle saved in `saved` into `entity`. Embedded assets are looked up in
+    /// `embedded`, by the id they had when they were saved.
+    pub(crate) fn load(
+        &self,
+        world: &mut World,
+        entity: Entity,
+        saved: &dyn Reflect,
+        embedded: &HashMap<HandleId, HandleUntyped>,
+    ) {
+        match (saved_handle(saved), &self.embed) {
+            (Some(SavedHandle::Path(path)), _) => (self.load)(world, entity, path),
+            (Some(SavedHandle::Embedded(id)), Some(embed)) => {
+                if let Some(handle) = embedded.get(&id) {
+                    (embed.insert)(world, entity, handle);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<A: Asset> FromType<Handle<A>> for ReflectSnapHandle {
+    fn from_type() -> Self {
+        ReflectSnapHandle {
+            path: |world, entity| {
+                let handle = world.get::<Handle<A>>(entity).unwrap();
+                world
+                    .get_resource::<AssetServer>()
+                    .ok_or_else(|| SnapError::MissingResource("AssetServer".to_string()))?
+                    .get_handle_path(handle)
+                    .map(|path| match path.label() {
+                        Some(label) => format!("{}#{}", path.path().display(), label),
+                        None => path.path().display().to_string(),
+                    })
+                    .ok_or_else(|| SnapError::MissingAssetPath {
+                        handle: std::any::type_name::<Handle<A>>().to_string(),
+                        entity,
+                    })
+            },
+            load: |world, entity, path| {
+                let handle = world.resource::<AssetServer>().load::<A, _>(path);
+                world.entity_mut(entity).insert(handle);
+            },
+            embed: None,
+        }
+    }
+}
+
+impl EmbedAsset {
+    fn new<A: Asset + Clone>() -> Self {
+        EmbedAsset {
+            id: |world, entity| world.get::<Handle<A>>(entity).unwrap().id,
+            capture: |world, id| {
+                let asset = world.get_resource::<Assets<A>>()?.get(id)?;
+                Some(Box::new(asset.clone()))
+            },
+            add: |world, asset| {
+                let asset = asset.as_any().downcast_ref::<A>().unwrap().clone();
+                let mut assets = world.get_resource_mut::<Assets<A>>().ok_or_else(|| {
+                    SnapError::MissingResource(std::any::type_name::<Assets<A>>().to_string())
+                })?;
+                Ok(assets.add(asset).clone_untyped())
+            },
+            insert: |world, entity, handle| {
+                world.entity_mut(entity).insert(handle.clone().typed::<A>());
+            },
+        }
+    }
+}
+
+/// An embedded asset, cloned from its `Assets` collection.
+pub(crate) trait AssetValue: Send + Sync {
+    fn clone_asset(&self) -> Box<dyn AssetValue>;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<A: Asset + Clone> AssetValue for A {
+    fn clone_asset(&self) -> Box<dyn AssetValue> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// An asset embedded in a snapshot, by the id of the handles that pointed to it.
+pub(crate) struct EmbeddedAsset {
+    pub id: HandleId,
+    /// Type name of the handles, e.g. `Handle<Image>`.
+    pub handle_type: &'static str,
+    pub asset: Box<dyn AssetValue>,
+}
+
+impl Clone for EmbeddedAsset {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            handle_type: self.handle_type,
+            asset: self.asset.clone_asset(),
+        }
+    }
+}
+
+impl std::fmt::Debug for EmbeddedAsset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmbeddedAsset")
+            .field("id", &self.id)
+            .field("handle_type", &self.handle_type)
+            .finish()
+    }
+}
+
+/// Reads a handle stored by `ReflectSnapHandle::save`.
+pub(crate) fn saved_handle(saved: &dyn Reflect) -> Option<SavedHandle<'_>> {
+    let saved = match saved.reflect_ref() {
+        ReflectRef::Struct(saved) => saved,
+        _ => return None,
+    };
+    if let Some(path) = saved.field("path") {
+        path.downcast_ref::<String>()
+            .map(|path| SavedHandle::Path(path))
+    } else {
+        saved
+            .field("embedded")
+            .and_then(|id| id.downcast_ref::<HandleId>())
+            .map(|id| SavedHandle::Embedded(*id))
+    }
+}
+
+/// Whether a reflected value holds a `Handle` in one of its fields. Such handles are saved as the
+/// runtime ids they had, since only `Handle` components are saved by asset path.
+pub(crate) fn contains_handle(value: &dyn Reflect) -> bool {
+    match value.reflect_ref() {
+        ReflectRef::Struct(value)
+            if value.type_name().starts_with("bevy_asset::handle::Handle<") =>
+        {
+            true
+        }
+        ReflectRef::Struct(value) => value.iter_fields().any(contains_handle),
+        ReflectRef::TupleStruct(value) => value.iter_fields().any(contains_handle),
+        ReflectRef::Tuple(value) => value.iter_fields().any(contains_handle),
+        ReflectRef::List(value) => value.iter().any(contains_handle),
+        ReflectRef::Map(value) => value
+            .iter()
+            .any(|(key, value)| contains_handle(key) || contains_handle(value)),
+        ReflectRef::Value(_) => false,
+    }
+}
+
+/// Registers `Handle<A>` as a component that is saved by asset path, see `ReflectSnapHandle`.
+pub fn register_asset_handle<A: Asset>(registry: &mut TypeRegistryInternal) {
+    registry.register::<Handle<A>>();
+    let registration = registry.get_mut(TypeId::of::<Handle<A>>()).unwrap();
+    registration.insert(<ReflectComponent as FromType<Handle<A>>>::from_type());
+    registration.insert(<ReflectSnapHandle as FromType<Handle<A>>>::from_type());
+}
+
+/// Like `register_asset_handle`, but assets without a path, e.g. generated meshes or images, are
+/// embedded in the snapshot instead of failing the save.
+///
+/// Each asset is embedded once, no matter how many handles point to it. Loading the snapshot adds
+/// the assets to `Assets<A>` again, and points the restored handles to the new assets.
+pub fn register_embedded_asset<A: Asset + Clone>(registry: &mut TypeRegistryInternal) {
+    register_asset_handle::<A>(registry);
+    let registration = registry.get_mut(TypeId::of::<Handle<A>>()).unwrap();
+    let mut snap_handle = registration.data::<ReflectSnapHandle>().unwrap().clone();
+    snap_handle.embed = Some(EmbedAsset::new::<A>());
+    registration.insert(snap_handle);
+}
@@ -0,0 +1,191 @@
+use std::any::TypeId;
+
+use bevy::{
+    prelude::*,
+    transform::components::GlobalTransform,
+    utils::{HashMap, HashSet},
+};
+
+/// Hierarchy components are never saved or restored through reflection, as they contain raw
+/// `Entity` ids. Relationships between tagged entities are tracked by snapshot id instead.
+pub(crate) fn is_hierarchy_type(type_id: TypeId) -> bool {
+    type_id == TypeId::of::<Parent>()
+        || type_id == TypeId::of::<Children>()
+        || type_id == TypeId::of::<PreviousParent>()
+}
+
+/// The desired place of a tracked entity in the hierarchy, resolved to world entities.
+pub(crate) struct HierarchyEntry {
+    pub entity: Entity,
+    pub parent: Option<Entity>,
+    pub children: Vec<Entity>,
+}
+
+/// Makes the `Parent`/`Children` relationships between the `selected` tracked entities match
+/// `entries`.
+///
+/// Relationships to entities that are not tracked are left alone, i.e. untracked children stay
+/// attached to their parents, and tracked entities with an untracked parent are not detached.
+/// Relationships to tracked entities that are not selected, i.e. left out by a `LoadFilter`, are
+/// left alone as well.
+pub(crate) fn restore_hierarchy(
+    world: &mut World,
+    entries: &[HierarchyEntry],
+    tracked: &HashSet<Entity>,
+    selected: &HashSet<Entity>,
+) {
+    // first, move every entity to its saved parent
+    for entry in entries {
+        let current_parent = world.get::<Parent>(entry.entity).map(|parent| parent.0);
+        if current_parent == entry.parent {
+            continue;
+        }
+        match (current_parent, entry.parent) {
+            (Some(current_parent), _)
+                if tracked.contains(&current_parent) && !selected.contains(&current_parent) =>
+            {
+                continue;
+            }
+            (Some(current_parent), None) if !tracked.contains(&current_parent) => {
+                // the entity was not saved with a parent, but it's attached to something we don't track
+                continue;
+            }
+            (Some(current_parent), _) => {
+                world
+                    .entity_mut(current_parent)
+                    .remove_children(&[entry.entity]);
+            }
+            (None, _) => {}
+        }
+        if let Some(parent) = entry.parent {
+            world.entity_mut(parent).push_children(&[entry.entity]);
+        }
+    }
+
+    // then restore the order of children, keeping the ones that aren't in the entry first: untracked
+    // ones, unselected ones, and tracked ones added since the snapshot was taken
+    for entry in entries {
+        let listed = entry.children.iter().copied().collect::<HashSet<_>>();
+        let mut children = world
+            .get::<Children>(entry.entity)
+            .map(|children| {
+                children
+                    .iter()
+                    .copied()
+                    .filter(|child| !listed.contains(child))
+                    .filter(|child| {
+                        world.get::<Parent>(*child).map(|parent| parent.0) == Some(entry.entity)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        // saved children that stayed with an unselected parent aren't added back
+        children.extend(entry.children.iter().copied().filter(|child| {
+            world.get::<Parent>(*child).map(|parent| parent.0) == Some(entry.entity)
+        }));
+
+        if children.is_empty() {
+            world.entity_mut(entry.entity).remove::<Children>();
+        } else {
+            world
+                .entity_mut(entry.entity)
+                .insert(Children::with(&children));
+        }
+    }
+}
+
+/// Despawns `entities` along with their descendants, except for the ones in `keep`, which are
+/// detached first and keep their own descendants.
+///
+/// Children are only removed from the `Children` of parents that stay, so despawning a whole
+/// subtree doesn't rewrite every level of it on the way.
+pub(crate) fn despawn_batch(world: &mut World, entities: &[Entity], keep: &HashSet<Entity>) {
+    let mut despawned = HashSet::default();
+    let mut parents = entities
+        .iter()
+        .copied()
+        .filter(|entity| world.get_entity(*entity).is_some())
+        .collect::<Vec<_>>();
+    let roots = parents.clone();
+    while let Some(parent) = parents.pop() {
+        if !despawned.insert(parent) {
+            continue;
+        }
+        let children = world
+            .get::<Children>(parent)
+            .map(|children| children.to_vec())
+            .unwrap_or_default();
+        for child in children {
+            if keep.contains(&child) {
+                world.entity_mut(parent).remove_children(&[child]);
+            } else {
+                parents.push(child);
+            }
+        }
+    }
+
+    // detach the roots from parents that stay, each parent at once
+    let mut detached = HashMap::<Entity, Vec<Entity>>::default();
+    for root in roots {
+        if let Some(parent) = world.get::<Parent>(root) {
+            if !despawned.contains(&parent.0) {
+                detached.entry(parent.0).or_default().push(root);
+            }
+        }
+    }
+    for (parent, children) in detached {
+        if let Some(mut parent) = world.get_entity_mut(parent) {
+            parent.remove_children(&children);
+        }
+    }
+
+    for entity in despawned {
+        world.despawn(entity);
+    }
+}
+
+/// Recomputes `GlobalTransform` for the given entities and all their descendants.
+pub(crate) fn propagate_transforms(world: &mut World, entities: &[Entity]) {
+    let entity_set = entities.iter().copied().collect::<HashSet<_>>();
+    let mut visited = HashSet::default();
+
+    for &entity in entities {
+        // only start from the top-most restored entities, the rest is handled recursively
+        let parent = world.get::<Parent>(entity).map(|parent| parent.0);
+        if parent.map_or(false, |parent| entity_set.contains(&parent)) {
+            continue;
+        }
+        let parent_global = parent.and_then(|parent| world.get::<GlobalTransform>(parent).copied());
+        propagate_recursive(world, entity, parent_global, &mut visited);
+    }
+}
+
+fn propagate_recursive(
+    world: &mut World,
+    entity: Entity,
+    parent_global: Option<GlobalTransform>,
+    visited: &mut HashSet<Entity>,
+) {
+    if !visited.insert(entity) {
+        return;
+    }
+    let transform = match world.get::<Transform>(entity) {
+        Some(transform) => *transform,
+        None => return,
+    };
+    let global = match parent_global {
+        Some(parent_global) => parent_global.mul_transform(transform),
+        None => GlobalTransform::from(transform),
+    };
+    if let Some(mut global_transform) = world.get_mut::<GlobalTransform>(entity) {
+        *global_transform = global;
+    }
+
+    let children = world
+        .get::<Children>(entity)
+        .map(|children| children.to_vec())
+        .unwrap_or_default();
+    for child in children {
+        propagate_recursive(world, child, Some(global), visited);
+    }
+}
@@ -0,0 +1,205 @@
+use std::{any::TypeId, sync::Arc};
+
+use bevy::{ecs::component::Component, prelude::*, utils::HashSet};
+
+use crate::{LoadFilter, LoadMode, LoadReport, SnapRegistry, SnapType, SnapshotId, WorldSnapshot};
+
+type WorldHook = Arc<dyn Fn(&mut World) + Send + Sync>;
+type EntityHook = Arc<dyn Fn(&mut World, Entity) + Send + Sync>;
+
+#[derive(Clone)]
+struct ComponentHook {
+    type_id: TypeId,
+    hook: EntityHook,
+}
+
+#[derive(Clone)]
+struct ResourceHook {
+    type_id: TypeId,
+    type_name: &'static str,
+    exists: fn(&World) -> bool,
+    hook: WorldHook,
+}
+
+/// Functions that run before a snapshot is taken, and after one has been loaded.
+///
+/// Use these to prepare state that needs to be captured, or to rebuild derived state (physics
+/// handles, caches, spatial indices) after loading. Register them in `SnapType::add_hooks`.
+///
+/// Hooks can save and load snapshots themselves, e.g. to keep a backup before loading.
+#[derive(Clone, Default)]
+pub struct SnapHooks {
+    pre_save: Vec<WorldHook>,
+    post_load: Vec<WorldHook>,
+    pre_save_components: Vec<ComponentHook>,
+    post_load_components: Vec<ComponentHook>,
+    pre_save_resources: Vec<ResourceHook>,
+    post_load_resources: Vec<ResourceHook>,
+}
+
+impl SnapHooks {
+    /// Runs `hook` before every snapshot is taken.
+    pub fn pre_save(&mut self, hook: impl Fn(&mut World) + Send + Sync + 'static) -> &mut Self {
+        self.pre_save.push(Arc::new(hook));
+        self
+    }
+
+    /// Runs `hook` after every snapshot is loaded.
+    pub fn post_load(&mut self, hook: impl Fn(&mut World) + Send + Sync + 'static) -> &mut Self {
+        self.post_load.push(Arc::new(hook));
+        self
+    }
+
+    /// Runs `hook` for every tracked entity with a `C` before a snapshot is taken.
+    pub fn pre_save_component<C: Component>(
+        &mut self,
+        hook: impl Fn(&mut World, Entity) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.pre_save_components.push(ComponentHook {
+            type_id: TypeId::of::<C>(),
+            hook: Arc::new(hook),
+        });
+        self
+    }
+
+    /// Runs `hook` for every restored entity with a `C` after a snapshot is loaded.
+    pub fn post_load_component<C: Component>(
+        &mut self,
+        hook: impl Fn(&mut World, Entity) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.post_load_components.push(ComponentHook {
+            type_id: TypeId::of::<C>(),
+            hook: Arc::new(hook),
+        });
+        self
+    }
+
+    /// Runs `hook` before a snapshot is taken, if the resource `R` exists.
+    pub fn pre_save_resource<R: Send + Sync + 'static>(
+        &mut self,
+        hook: impl Fn(&mut World) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.pre_save_resources.push(ResourceHook {
+            type_id: TypeId::of::<R>(),
+            type_name: std::any::type_name::<R>(),
+            exists: |world| world.contains_resource::<R>(),
+            hook: Arc::new(hook),
+        });
+        self
+    }
+
+    /// Runs `hook` after a snapshot is loaded, if the resource `R` was restored.
+    pub fn post_load_resource<R: Send + Sync + 'static>(
+        &mut self,
+        hook: impl Fn(&mut World) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.post_load_resources.push(ResourceHook {
+            type_id: TypeId::of::<R>(),
+            type_name: std::any::type_name::<R>(),
+            exists: |world| world.contains_resource::<R>(),
+            hook: Arc::new(hook),
+        });
+        self
+    }
+}
+
+/// The hooks of `T`, cloned out of the registry, so it stays in the world while they run.
+fn hooks<T: SnapType>(world: &World) -> SnapHooks {
+    world
+        .get_resource::<SnapRegistry<T>>()
+        .map(|registry| registry.hooks.clone())
+        .unwrap_or_default()
+}
+
+/// Runs global pre-save hooks, then per-type hooks. Component hooks only run for the tracked
+/// entities `in_scope` accepts.
+pub(crate) fn run_pre_save<T: SnapType>(
+    world: &mut World,
+    in_scope: impl Fn(&World, Entity) -> bool,
+) {
+    let hooks = hooks::<T>(world);
+    for hook in hooks.pre_save.iter() {
+        hook(world);
+    }
+
+    for component_hook in hooks.pre_save_components.iter() {
+        let entities = entities_with(world, component_hook.type_id, |world, entity| {
+            world.get::<SnapshotId<T>>(entity).is_some() && in_scope(world, entity)
+        });
+        for entity in entities {
+            (component_hook.hook)(world, entity);
+        }
+    }
+
+    for resource_hook in hooks.pre_save_resources.iter() {
+        if (resource_hook.exists)(world) {
+            (resource_hook.hook)(world);
+        }
+    }
+}
+
+/// Runs per-type post-load hooks, then global hooks, so global hooks see rebuilt state.
+pub(crate) fn run_post_load<T: SnapType>(
+    world: &mut World,
+    snapshot: &WorldSnapshot<T>,
+    report: &LoadReport,
+    mode: LoadMode,
+    filter: &LoadFilter,
+) {
+    let hooks = hooks::<T>(world);
+    let restored = report
+        .entities
+        .values()
+        .map(|mapping| mapping.new)
+        .collect::<HashSet<_>>();
+
+    for component_hook in hooks.post_load_components.iter() {
+        if !filter.includes_component(component_hook.type_id) {
+            continue;
+        }
+        let entities = entities_with(world, component_hook.type_id, |_, entity| {
+            restored.contains(&entity)
+        });
+        for entity in entities {
+            (component_hook.hook)(world, entity);
+        }
+    }
+
+    if mode != LoadMode::Additive {
+        for resource_hook in hooks.post_load_resources.iter() {
+            let restored = filter.includes_resource(resource_hook.type_id)
+                && snapshot
+                    .resources
+                    .iter()
+                    .any(|resource| resource.type_name() == resource_hook.type_name);
+            if restored {
+                (resource_hook.hook)(world);
+            }
+        }
+    }
+
+    for hook in hooks.post_load.iter() {
+        hook(world);
+    }
+}
+
+/// Returns the entities that have the component `type_id` and satisfy `predicate`, in entity order.
+fn entities_with(
+    world: &World,
+    type_id: TypeId,
+    predicate: impl Fn(&World, Entity) -> bool,
+) -> Vec<Entity> {
+    let component_id = match world.components().get_id(type_id) {
+        Some(component_id) => component_id,
+        None => return Vec::new(),
+    };
+    let mut entities = world
+        .archetypes()
+        .iter()
+        .filter(|archetype| archetype.contains(component_id))
+        .flat_map(|archetype| archetype.entities().iter().copied())
+        .filter(|entity| predicate(world, *entity))
+        .collect::<Vec<_>>();
+    entities.sort_by_key(|entity| (entity.id(), entity.generation()));
+    entities
+}
@@ -1,19 +1,145 @@
 use std::marker::PhantomData;
 
-use bevy::{prelude::*, reflect::TypeRegistry};
+use auto_tag::{AutoTagFilter, AutoTagFilters};
+use bevy::{
+    ecs::query::{FilterFetch, WorldQuery},
+    prelude::*,
+    reflect::TypeRegistry,
+};
 
+mod auto_tag;
+mod chunks;
 mod commands;
+mod error;
+mod flow;
+mod handles;
+mod hierarchy;
+mod hooks;
+mod load_filter;
+mod load_report;
+mod markers;
 mod reflect_resource;
+mod snap_clone;
+mod snap_registry;
+mod snapshot_id;
 mod snapshot_id_provider;
+mod transient;
+mod validation;
+mod world_copy;
+mod world_ext;
 mod world_snapshot;
 
+pub use chunks::SnapChunks;
 pub use commands::*;
-pub use reflect_resource::ReflectResource;
+pub use error::*;
+pub use flow::SnapFlow;
+pub use handles::{register_asset_handle, register_embedded_asset, ReflectSnapHandle};
+pub use hooks::SnapHooks;
+pub use load_filter::LoadFilter;
+pub use load_report::*;
+pub use markers::{register_snap_markers, SnapExclude, SnapPinned};
+pub use reflect_resource::{register_resource_from_reflect, ReflectDefault, ReflectResource};
+pub use snap_clone::{register_snap_clone, ReflectSnapClone};
+pub use snap_registry::SnapRegistry;
+pub use snapshot_id::*;
 pub use snapshot_id_provider::*;
+pub use transient::{ReflectSnapTransient, SnapTransientFields};
+pub use validation::*;
+pub use world_ext::SnapWorldExt;
 pub use world_snapshot::*;
 
+pub use bevy_snap_derive::SnapType;
+
+// lets the derive macro refer to `::bevy_snap` from within this crate
+extern crate self as bevy_snap;
+
+/// Used by `#[derive(SnapType)]`.
+#[doc(hidden)]
+pub mod __macro {
+    use std::{any::TypeId, marker::PhantomData};
+
+    use bevy::{
+        ecs::{component::Component, system::Resource},
+        prelude::*,
+        reflect::{FromReflect, FromType, GetTypeRegistration, TypeRegistryInternal},
+    };
+
+    pub use bevy::reflect::TypeRegistry;
+
+    use crate::ReflectResource;
+
+    pub fn register_component<C>(registry: &mut TypeRegistryInternal)
+    where
+        C: Component + Reflect + FromWorld + GetTypeRegistration,
+    {
+        registry.register::<C>();
+        let registration = registry.get_mut(TypeId::of::<C>()).unwrap();
+        registration.insert(<ReflectComponent as FromType<C>>::from_type());
+    }
+
+    pub fn register_resource<R>(
+        registry: &mut TypeRegistryInternal,
+        reflect_resource: ReflectResource,
+    ) where
+        R: Resource + GetTypeRegistration,
+    {
+        registry.register::<R>();
+        let registration = registry.get_mut(TypeId::of::<R>()).unwrap();
+        registration.insert(reflect_resource);
+    }
+
+    /// Picks how `R` is created when it's loaded, by autoref specialization: with `FromWorld` if
+    /// it can, with `FromReflect` otherwise, and else through its `ReflectDefault` type data.
+    ///
+    /// Call `(&&&ResourceConstructor::<R>::default()).reflect_resource()` with all three traits in
+    /// scope.
+    pub struct ResourceConstructor<R>(PhantomData<R>);
+
+    impl<R> Default for ResourceConstructor<R> {
+        fn default() -> Self {
+            Self(PhantomData)
+        }
+    }
+
+    pub trait ViaFromWorld {
+        fn reflect_resource(&self) -> ReflectResource;
+    }
+
+    impl<R: Resource + Reflect + FromWorld> ViaFromWorld for &&ResourceConstructor<R> {
+        fn reflect_resource(&self) -> ReflectResource {
+            <ReflectResource as FromType<R>>::from_type()
+        }
+    }
+
+    pub trait ViaFromReflect {
+        fn reflect_resource(&self) -> ReflectResource;
+    }
+
+    impl<R: Resource + FromReflect> ViaFromReflect for &ResourceConstructor<R> {
+        fn reflect_resource(&self) -> ReflectResource {
+            ReflectResource::from_reflect::<R>()
+        }
+    }
+
+    pub trait ViaReflectDefault {
+        fn reflect_resource(&self) -> ReflectResource;
+    }
+
+    impl<R: Resource + Reflect> ViaReflectDefault for ResourceConstructor<R> {
+        fn reflect_resource(&self) -> ReflectResource {
+            ReflectResource::without_constructor::<R>()
+        }
+    }
+}
+
 pub trait SnapType: 'static + Send + Sync + Default {
     fn add_types(registry: &mut TypeRegistry);
+
+    /// Registers functions to run before saving and after loading, see `SnapHooks`.
+    fn add_hooks(_hooks: &mut SnapHooks) {}
+
+    /// Registers the `State`s and `Events` to save and load, see `SnapFlow`.
+    fn add_flow(_flow: &mut SnapFlow) {}
 }
 
 #[derive(Default)]
@@ -21,17 +147,114 @@ pub struct SnapPlugin<T>
 where
     T: SnapType,
 {
+    duplicate_id_policy: DuplicateIdPolicy,
+    parallel_capture: bool,
+    app_types: Vec<String>,
+    auto_tag: Vec<fn() -> AutoTagFilter>,
     t: PhantomData<T>,
 }
 
+impl<T: SnapType> SnapPlugin<T> {
+    /// Automatically tags entities matching the query filter `F` with ids from the
+    /// `SnapshotIdProvider`, e.g. `.auto_tag::<With<Player>>()`.
+    ///
+    /// Tagging happens in `CoreStage::PreUpdate`, in entity order, so peers spawning the same
+    /// entities get the same ids. Entities matching several auto tag filters are tagged once.
+    pub fn auto_tag<F>(mut self) -> Self
+    where
+        F: WorldQuery + 'static,
+        F::Fetch: FilterFetch,
+    {
+        self.auto_tag.push(auto_tag::filtered::<T, F>);
+        self
+    }
+
+    /// Automatically tags entities that have any of the components registered in the `SnapType`.
+    pub fn auto_tag_registered(mut self) -> Self {
+        self.auto_tag.push(auto_tag::registered::<T>);
+        self
+    }
+
+    /// Sets what happens when an entity is tagged with a `SnapshotId` that is already in use.
+    pub fn with_duplicate_id_policy(mut self, policy: DuplicateIdPolicy) -> Self {
+        self.duplicate_id_policy = policy;
+        self
+    }
+
+    /// Captures snapshots on the `ComputeTaskPool`, one task per archetype. Worth it for large
+    /// worlds, the snapshots are identical either way.
+    pub fn with_parallel_capture(mut self) -> Self {
+        self.parallel_capture = true;
+        self
+    }
+
+    /// Also saves and loads the types with the given names, looked up in the app's `TypeRegistry`
+    /// at startup, e.g. a list from a config file. See `SnapRegistry::register_names`.
+    pub fn with_app_types(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.app_types.extend(names.into_iter().map(Into::into));
+        self
+    }
+}
+
 impl<T: 'static + SnapType> Plugin for SnapPlugin<T> {
     fn build(&self, app: &mut App) {
         app.init_resource::<SnapRegistry<T>>();
         app.init_resource::<SnapshotIdProvider<T>>();
+        app.insert_resource(SnapSettings::<T> {
+            duplicate_id_policy: self.duplicate_id_policy,
+            parallel_capture: self.parallel_capture,
+            t: default(),
+        });
         app.add_event::<SaveEvent<T>>();
+        app.add_event::<LoadEvent<T>>();
+        app.add_event::<SnapErrorEvent<T>>();
+        app.add_event::<DuplicateSnapshotIdEvent<T>>();
+        app.add_system_to_stage(CoreStage::PostUpdate, detect_duplicate_ids::<T>);
+        if !self.app_types.is_empty() {
+            let names = self.app_types.clone();
+            // wait until all plugins had a chance to register their types
+            app.add_startup_system_to_stage(
+                StartupStage::PreStartup,
+                (move |world: &mut World| register_app_types::<T>(world, &names))
+                    .exclusive_system(),
+            );
+        }
+        if !self.auto_tag.is_empty() {
+            app.insert_resource(AutoTagFilters::<T> {
+                filters: self.auto_tag.iter().map(|filter| filter()).collect(),
+                t: default(),
+            });
+            app.add_system_to_stage(
+                CoreStage::PreUpdate,
+                auto_tag::auto_tag::<T>.exclusive_system(),
+            );
+        }
     }
 }
 
+fn register_app_types<T: SnapType>(world: &mut World, names: &[String]) {
+    let app_registry = match world.get_resource::<TypeRegistry>() {
+        Some(app_registry) => app_registry.clone(),
+        None => {
+            error!("can't register snapshot types by name, the app has no TypeRegistry");
+            return;
+        }
+    };
+    let result = world
+        .resource_mut::<SnapRegistry<T>>()
+        .register_names(&app_registry.read(), names);
+    if let Err(error) = result {
+        error!("{}", error);
+    }
+}
+
+/// Settings chosen when adding the `SnapPlugin`.
+pub(crate) struct SnapSettings<T: SnapType> {
+    pub duplicate_id_policy: DuplicateIdPolicy,
+    pub parallel_capture: bool,
+    t: PhantomData<T>,
+}
+
 pub struct SaveEvent<T: SnapType> {
     pub snapshot: WorldSnapshot<T>,
 }
@@ -42,27 +265,19 @@ impl<T: SnapType> From<WorldSnapshot<T>> for SaveEvent<T> {
     }
 }
 
-struct SnapRegistry<T: SnapType> {
-    type_registry: TypeRegistry,
-    t: PhantomData<T>,
-}
-
-impl<T: SnapType> Default for SnapRegistry<T> {
-    fn default() -> Self {
-        let mut type_registry = TypeRegistry::default();
-        T::add_types(&mut type_registry);
-        Self {
-            type_registry,
-            t: default(),
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
-    use bevy::prelude::*;
+    use bevy::{
+        asset::{AssetPlugin, FileAssetIo, HandleId},
+        ecs::{event::Events, system::Command},
+        prelude::*,
+        reflect::{DynamicTupleStruct, FromReflect, GetTypeRegistration, TypeRegistry, TypeUuid},
+        tasks::{IoTaskPool, TaskPool},
+    };
+
+    use crate::*;
 
-    fn single<'s, T>(app: &'s mut App) -> &'s T
+    fn single<T>(app: &mut App) -> &T
     where
         T: Component,
     {
@@ -74,6 +289,29 @@ mod tests {
         value: i32,
     }
 
+    #[derive(Default)]
+    struct TestSnap;
+
+    impl SnapType for TestSnap {
+        fn add_types(registry: &mut TypeRegistry) {
+            registry.write().register::<Transform>();
+        }
+    }
+
+    fn test_registry() -> TypeRegistry {
+        SnapRegistry::<TestSnap>::default().type_registry
+    }
+
+    fn spawn_tracked(world: &mut World, id: u32, x: f32) -> Entity {
+        world
+            .spawn()
+            .insert_bundle(TransformBundle::from_transform(Transform::from_xyz(
+                x, 0., 0.,
+            )))
+            .insert(SnapshotId::<TestSnap>::new(id))
+            .id()
+    }
+
     #[test]
     fn it_works() {
         fn startup(mut commands: Commands) {
@@ -96,4 +334,1444 @@ mod tests {
             })
             .run();
     }
+
+    #[test]
+    fn restores_hierarchy() {
+        let mut world = World::default();
+        let registry = test_registry();
+
+        let parent = spawn_tracked(&mut world, 0, 1.);
+        let child = spawn_tracked(&mut world, 1, 1.);
+        let untracked = world.spawn().insert_bundle(TransformBundle::default()).id();
+        world.entity_mut(parent).push_children(&[child]);
+        world.entity_mut(child).push_children(&[untracked]);
+
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+
+        // detach the child and add a new tracked entity with an untracked child
+        world.entity_mut(parent).remove_children(&[child]);
+        let extra = spawn_tracked(&mut world, 2, 0.);
+        let extra_child = world.spawn().id();
+        world.entity_mut(extra).push_children(&[extra_child]);
+
+        snapshot
+            .write_to_world(&mut world, registry, LoadMode::Replace, &LoadFilter::all())
+            .unwrap();
+
+        assert_eq!(world.get::<Parent>(child).unwrap().0, parent);
+        assert_eq!(&**world.get::<Children>(parent).unwrap(), &[child]);
+        assert_eq!(world.get::<Parent>(untracked).unwrap().0, child);
+        assert!(world.get_entity(extra).is_none());
+        assert!(world.get_entity(extra_child).is_none());
+        assert_eq!(
+            world.get::<GlobalTransform>(untracked).unwrap().translation,
+            Vec3::new(2., 0., 0.)
+        );
+    }
+
+    #[test]
+    fn despawns_tracked_subtrees() {
+        let mut world = World::default();
+        let registry = test_registry();
+
+        let kept = spawn_tracked(&mut world, 0, 0.);
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+
+        // a tracked subtree, held by an untracked parent that stays
+        let holder = world.spawn().id();
+        let sibling = world.spawn().id();
+        let extra = spawn_tracked(&mut world, 1, 0.);
+        let extra_child = spawn_tracked(&mut world, 2, 0.);
+        let leaf = world.spawn().id();
+        world.entity_mut(holder).push_children(&[sibling, extra]);
+        world.entity_mut(extra).push_children(&[extra_child]);
+        world.entity_mut(extra_child).push_children(&[leaf]);
+
+        snapshot
+            .write_to_world(&mut world, registry, LoadMode::Replace, &LoadFilter::all())
+            .unwrap();
+
+        assert!(world.get_entity(kept).is_some());
+        assert!(world.get_entity(extra).is_none());
+        assert!(world.get_entity(extra_child).is_none());
+        assert!(world.get_entity(leaf).is_none());
+        assert_eq!(&**world.get::<Children>(holder).unwrap(), &[sibling]);
+    }
+
+    #[test]
+    fn restores_id_provider() {
+        let mut world = World::default();
+        let registry = test_registry();
+        world.init_resource::<SnapshotIdProvider<TestSnap>>();
+
+        let id = world.resource_mut::<SnapshotIdProvider<TestSnap>>().next();
+        world.spawn().insert(id);
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+
+        // loading into a fresh world must not reuse the id of the restored entity
+        let mut fresh_world = World::default();
+        fresh_world.init_resource::<SnapshotIdProvider<TestSnap>>();
+        snapshot
+            .write_to_world(
+                &mut fresh_world,
+                registry,
+                LoadMode::Replace,
+                &LoadFilter::all(),
+            )
+            .unwrap();
+        let next_id = fresh_world
+            .resource_mut::<SnapshotIdProvider<TestSnap>>()
+            .next();
+        assert_eq!(next_id.id(), &SnapshotKey::Index(1));
+    }
+
+    #[test]
+    fn recycles_released_ids() {
+        let mut provider = SnapshotIdProvider::<TestSnap>::default();
+        let first = provider.next().id().index().unwrap();
+        let second = provider.next().id().index().unwrap();
+        provider.release(first);
+        assert_eq!(provider.next().id().index(), Some(first));
+        assert_eq!(provider.next().id().index(), Some(second + 1));
+
+        provider.reserve(u32::MAX);
+        assert!(provider.try_next().is_none());
+    }
+
+    #[test]
+    fn merge_keeps_children_added_since_the_snapshot() {
+        let mut world = World::default();
+        let registry = test_registry();
+
+        let parent = spawn_tracked(&mut world, 0, 0.);
+        let child = spawn_tracked(&mut world, 1, 0.);
+        world.entity_mut(parent).push_children(&[child]);
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+
+        let added = spawn_tracked(&mut world, 2, 0.);
+        world.entity_mut(parent).push_children(&[added]);
+        snapshot
+            .write_to_world(&mut world, registry, LoadMode::Merge, &LoadFilter::all())
+            .unwrap();
+
+        assert_eq!(world.get::<Parent>(added).unwrap().0, parent);
+        assert_eq!(world.get::<Parent>(child).unwrap().0, parent);
+        assert_eq!(&**world.get::<Children>(parent).unwrap(), &[added, child]);
+    }
+
+    #[test]
+    fn load_modes() {
+        let mut world = World::default();
+        let registry = test_registry();
+        world.init_resource::<SnapshotIdProvider<TestSnap>>();
+
+        let id = world.resource_mut::<SnapshotIdProvider<TestSnap>>().next();
+        let entity = world.spawn().insert(id).insert(Transform::default()).id();
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+
+        // merging never despawns
+        let id = world.resource_mut::<SnapshotIdProvider<TestSnap>>().next();
+        let extra = world.spawn().insert(id).id();
+        snapshot
+            .write_to_world(
+                &mut world,
+                registry.clone(),
+                LoadMode::Merge,
+                &LoadFilter::all(),
+            )
+            .unwrap();
+        assert!(world.get_entity(extra).is_some());
+
+        // additive loads spawn copies with fresh ids
+        snapshot
+            .write_to_world(&mut world, registry, LoadMode::Additive, &LoadFilter::all())
+            .unwrap();
+        let mut query = world.query::<(Entity, &SnapshotId<TestSnap>, &Transform)>();
+        let copies = query
+            .iter(&world)
+            .filter(|(e, _, _)| *e != entity)
+            .collect::<Vec<_>>();
+        assert_eq!(copies.len(), 1);
+        assert_eq!(copies[0].1.id(), &SnapshotKey::Index(2));
+    }
+
+    #[test]
+    fn additive_load_fails_when_ids_run_out() {
+        let mut world = World::default();
+        let registry = test_registry();
+        world.init_resource::<SnapshotIdProvider<TestSnap>>();
+        for _ in 0..2 {
+            let id = world.resource_mut::<SnapshotIdProvider<TestSnap>>().next();
+            world.spawn().insert(id);
+        }
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+
+        // only one id left for the two entities
+        world
+            .resource_mut::<SnapshotIdProvider<TestSnap>>()
+            .reserve(u32::MAX - 2);
+        let result =
+            snapshot.write_to_world(&mut world, registry, LoadMode::Additive, &LoadFilter::all());
+        assert_eq!(result.err(), Some(SnapError::OutOfIds));
+        assert_eq!(world.entities().len(), 2);
+        let mut id_provider = world.resource_mut::<SnapshotIdProvider<TestSnap>>();
+        assert_eq!(id_provider.next().id().index(), Some(u32::MAX - 1));
+    }
+
+    #[test]
+    fn sends_load_report() {
+        let mut app = App::new();
+        app.add_plugin(SnapPlugin::<TestSnap>::default());
+        let kept = app
+            .world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::named("kept"))
+            .insert(Transform::default())
+            .id();
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(
+            &app.world,
+            &app.world.resource::<SnapRegistry<TestSnap>>().type_registry,
+        );
+
+        app.world.entity_mut(kept).remove::<Transform>();
+        let extra = app
+            .world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::named("extra"))
+            .id();
+        LoadCommand::new(snapshot).write(&mut app.world);
+
+        let events = app.world.resource::<Events<LoadEvent<TestSnap>>>();
+        let report = &events.iter_current_update_events().next().unwrap().report;
+        assert_eq!(report.updated, vec!["kept".into()]);
+        assert_eq!(report.despawned, vec![("extra".into(), extra)]);
+        assert_eq!(
+            report.components_added,
+            vec![(kept, std::any::type_name::<Transform>())]
+        );
+        assert_eq!(
+            report.entities[&"kept".into()],
+            EntityMapping {
+                old: kept,
+                new: kept
+            }
+        );
+    }
+
+    #[test]
+    fn partial_load_keeps_links_to_unselected_entities() {
+        let mut world = World::default();
+        let registry = test_registry();
+        let player = world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::named("player"))
+            .insert(Transform::from_xyz(1., 0., 0.))
+            .id();
+        let cargo = world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::named("cargo"))
+            .id();
+        let ship = world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::named("ship"))
+            .push_children(&[player, cargo])
+            .id();
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+
+        world.get_mut::<Transform>(player).unwrap().translation.x = 2.;
+        let gem = world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::named("gem"))
+            .id();
+        let chest = world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::named("chest"))
+            .push_children(&[gem])
+            .id();
+
+        let filter = LoadFilter::none()
+            .with_entity("player")
+            .with_entity("chest")
+            .with_all_components();
+        snapshot
+            .write_to_world(&mut world, registry, LoadMode::Replace, &filter)
+            .unwrap();
+
+        assert_eq!(world.get::<Transform>(player).unwrap().translation.x, 1.);
+        assert_eq!(world.get::<Parent>(player).unwrap().0, ship);
+        assert_eq!(&**world.get::<Children>(ship).unwrap(), &[player, cargo]);
+        // the chest isn't in the snapshot, but the gem wasn't selected
+        assert!(world.get_entity(chest).is_none());
+        assert!(world.get_entity(gem).is_some());
+        assert!(world.get::<Parent>(gem).is_none());
+    }
+
+    #[test]
+    fn partial_load_leaves_children_with_unselected_parents() {
+        let mut world = World::default();
+        let registry = test_registry();
+        let child = world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::named("c"))
+            .id();
+        let parent = world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::named("p"))
+            .push_children(&[child])
+            .id();
+        let other = world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::named("q"))
+            .id();
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+
+        world.entity_mut(parent).remove_children(&[child]);
+        world.entity_mut(other).push_children(&[child]);
+        let filter = LoadFilter::none()
+            .with_entity("p")
+            .with_entity("c")
+            .with_all_components();
+        snapshot
+            .write_to_world(&mut world, registry, LoadMode::Replace, &filter)
+            .unwrap();
+
+        // the child stays with the unselected parent, and only it lists the child
+        assert_eq!(world.get::<Parent>(child).unwrap().0, other);
+        assert_eq!(&**world.get::<Children>(other).unwrap(), &[child]);
+        assert!(world.get::<Children>(parent).is_none());
+    }
+
+    #[test]
+    fn partial_load() {
+        #[derive(Component, Reflect, Default)]
+        #[reflect(Component, Resource)]
+        struct Score(i32);
+
+        #[derive(Default)]
+        struct ScoreSnap;
+
+        impl SnapType for ScoreSnap {
+            fn add_types(registry: &mut TypeRegistry) {
+                registry.write().register::<Transform>();
+                registry.write().register::<Score>();
+            }
+        }
+
+        let mut world = World::default();
+        let registry = SnapRegistry::<ScoreSnap>::default().type_registry;
+        world.insert_resource(Score(1));
+        let player = world
+            .spawn()
+            .insert(SnapshotId::<ScoreSnap>::named("player"))
+            .insert(Score(1))
+            .insert(Transform::from_xyz(1., 0., 0.))
+            .id();
+        let snapshot = WorldSnapshot::<ScoreSnap>::from_world(&world, &registry);
+
+        world.resource_mut::<Score>().0 = 2;
+        world.get_mut::<Score>(player).unwrap().0 = 2;
+        world.get_mut::<Transform>(player).unwrap().translation.x = 2.;
+        let other = world
+            .spawn()
+            .insert(SnapshotId::<ScoreSnap>::named("other"))
+            .id();
+
+        let filter = LoadFilter::none()
+            .with_entity("player")
+            .with_component::<Score>();
+        snapshot
+            .write_to_world(&mut world, registry, LoadMode::Replace, &filter)
+            .unwrap();
+
+        assert_eq!(world.get::<Score>(player).unwrap().0, 1);
+        assert_eq!(world.get::<Transform>(player).unwrap().translation.x, 2.);
+        assert_eq!(world.resource::<Score>().0, 2);
+        assert!(world.get_entity(other).is_some());
+    }
+
+    #[test]
+    fn reassigns_duplicate_ids() {
+        let mut app = App::new();
+        app.add_plugin(
+            SnapPlugin::<TestSnap>::default().with_duplicate_id_policy(DuplicateIdPolicy::Reassign),
+        );
+        let id = app
+            .world
+            .resource_mut::<SnapshotIdProvider<TestSnap>>()
+            .next();
+        let first = app.world.spawn().insert(id).id();
+        app.update();
+        let second = app
+            .world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::new(0))
+            .id();
+        app.update();
+
+        let first_id = app.world.get::<SnapshotId<TestSnap>>(first).unwrap();
+        let second_id = app.world.get::<SnapshotId<TestSnap>>(second).unwrap();
+        assert_eq!(first_id.id(), &SnapshotKey::Index(0));
+        assert_eq!(second_id.id(), &SnapshotKey::Index(1));
+    }
+
+    #[test]
+    fn reports_duplicates_when_ids_run_out() {
+        let mut app = App::new();
+        app.add_plugin(
+            SnapPlugin::<TestSnap>::default().with_duplicate_id_policy(DuplicateIdPolicy::Reassign),
+        );
+        let first = app
+            .world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::new(0))
+            .id();
+        app.update();
+        app.world
+            .resource_mut::<SnapshotIdProvider<TestSnap>>()
+            .reserve(u32::MAX - 1);
+        let second = app
+            .world
+            .spawn()
+            .insert(SnapshotId::<TestSnap>::new(0))
+            .id();
+        app.update();
+
+        let events = app
+            .world
+            .resource::<Events<DuplicateSnapshotIdEvent<TestSnap>>>();
+        let mut reader = events.get_reader();
+        let event = reader.iter(events).next().unwrap();
+        assert_eq!(event.id, SnapshotKey::Index(0));
+        assert_eq!(event.entities, vec![first, second]);
+    }
+
+    #[test]
+    fn auto_tags_entities() {
+        #[derive(Component)]
+        struct Marker;
+
+        let mut app = App::new();
+        app.add_plugin(
+            SnapPlugin::<TestSnap>::default()
+                .auto_tag::<With<Marker>>()
+                .auto_tag_registered(),
+        );
+        let marked = app.world.spawn().insert(Marker).id();
+        let registered = app.world.spawn().insert(Transform::default()).id();
+        let untracked = app.world.spawn().id();
+        app.update();
+
+        assert!(app.world.get::<SnapshotId<TestSnap>>(marked).is_some());
+        assert!(app.world.get::<SnapshotId<TestSnap>>(registered).is_some());
+        assert!(app.world.get::<SnapshotId<TestSnap>>(untracked).is_none());
+    }
+
+    #[test]
+    fn auto_tags_each_entity_once_in_entity_order() {
+        #[derive(Component)]
+        struct Marker;
+
+        let mut app = App::new();
+        app.add_plugin(
+            SnapPlugin::<TestSnap>::default()
+                .auto_tag::<With<Marker>>()
+                .auto_tag::<With<Transform>>(),
+        );
+        let registered = app.world.spawn().insert(Transform::default()).id();
+        let both = app
+            .world
+            .spawn()
+            .insert(Marker)
+            .insert(Transform::default())
+            .id();
+        let marked = app.world.spawn().insert(Marker).id();
+        app.update();
+
+        let id = |entity| app.world.get::<SnapshotId<TestSnap>>(entity).unwrap().id();
+        assert_eq!(id(registered), &SnapshotKey::Index(0));
+        assert_eq!(id(both), &SnapshotKey::Index(1));
+        assert_eq!(id(marked), &SnapshotKey::Index(2));
+        assert_eq!(
+            app.world
+                .resource::<SnapshotIdProvider<TestSnap>>()
+                .clone()
+                .next()
+                .id(),
+            &SnapshotKey::Index(3)
+        );
+    }
+
+    #[test]
+    fn runs_hooks() {
+        #[derive(Component, Default)]
+        struct Cache(f32);
+
+        #[derive(Default)]
+        struct HookSnap;
+
+        impl SnapType for HookSnap {
+            fn add_types(registry: &mut TypeRegistry) {
+                registry.write().register::<Transform>();
+            }
+
+            fn add_hooks(hooks: &mut SnapHooks) {
+                hooks
+                    .post_load_component::<Transform>(|world, entity| {
+                        let x = world.get::<Transform>(entity).unwrap().translation.x;
+                        world.entity_mut(entity).insert(Cache(x));
+                    })
+                    .pre_save(|world| world.insert_resource(Cache(-1.)));
+            }
+        }
+
+        let mut world = World::default();
+        world.init_resource::<SnapRegistry<HookSnap>>();
+        let entity = world
+            .spawn()
+            .insert(SnapshotId::<HookSnap>::new(0))
+            .insert(Transform::from_xyz(3., 0., 0.))
+            .id();
+        let snapshot = world.save_snapshot::<HookSnap>().unwrap();
+        assert_eq!(world.resource::<Cache>().0, -1.);
+
+        world.load_snapshot(&snapshot).unwrap();
+        assert_eq!(world.get::<Cache>(entity).unwrap().0, 3.);
+    }
+
+    #[test]
+    fn derive_adds_hooks() {
+        #[derive(Default)]
+        struct Saves(u32);
+
+        fn count_saves(hooks: &mut SnapHooks) {
+            hooks.pre_save(|world| world.get_resource_or_insert_with(Saves::default).0 += 1);
+        }
+
+        #[derive(Default, SnapType)]
+        #[snap(components(Transform), hooks = count_saves)]
+        struct DerivedSnap;
+
+        let mut world = World::default();
+        world.init_resource::<SnapRegistry<DerivedSnap>>();
+        world.save_snapshot::<DerivedSnap>().unwrap();
+        world.save_snapshot::<DerivedSnap>().unwrap();
+        assert_eq!(world.resource::<Saves>().0, 2);
+    }
+
+    #[test]
+    fn hooks_can_save_snapshots() {
+        struct Backup(Result<usize, SnapError>);
+
+        #[derive(Default)]
+        struct BackupSnap;
+
+        impl SnapType for BackupSnap {
+            fn add_types(registry: &mut TypeRegistry) {
+                registry.write().register::<Transform>();
+            }
+
+            fn add_hooks(hooks: &mut SnapHooks) {
+                hooks.post_load(|world| {
+                    let backup = world.save_snapshot::<BackupSnap>();
+                    world.insert_resource(Backup(backup.map(|backup| backup.entities.len())));
+                });
+            }
+        }
+
+        let mut world = World::default();
+        world.init_resource::<SnapRegistry<BackupSnap>>();
+        world
+            .spawn()
+            .insert(SnapshotId::<BackupSnap>::new(0))
+            .insert(Transform::default());
+        let snapshot = world.save_snapshot::<BackupSnap>().unwrap();
+        world.load_snapshot(&snapshot).unwrap();
+        assert_eq!(world.resource::<Backup>().0, Ok(1));
+    }
+
+    #[test]
+    fn load_errors() {
+        let mut world = World::default();
+        let snapshot = WorldSnapshot::<TestSnap>::default();
+        assert_eq!(
+            world.load_snapshot(&snapshot).unwrap_err(),
+            SnapError::MissingRegistry(std::any::type_name::<TestSnap>())
+        );
+
+        world.init_resource::<SnapRegistry<TestSnap>>();
+        world.spawn().insert(SnapshotId::<TestSnap>::new(0));
+        world.spawn().insert(SnapshotId::<TestSnap>::new(0));
+        assert_eq!(
+            world.load_snapshot(&snapshot).unwrap_err(),
+            SnapError::DuplicateId(SnapshotKey::Index(0))
+        );
+    }
+
+    #[test]
+    fn validate_reports_issues() {
+        let mut world = World::default();
+        world.spawn().insert(SnapshotId::<TestSnap>::named("a"));
+        world.spawn().insert(SnapshotId::<TestSnap>::named("a"));
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &test_registry());
+
+        assert_eq!(
+            snapshot.validate(),
+            Err(vec![SnapshotIssue::DuplicateId("a".into())])
+        );
+    }
+
+    #[test]
+    fn parallel_capture_matches_serial() {
+        let mut world = World::default();
+        let registry = test_registry();
+        for i in 0..100 {
+            let entity = spawn_tracked(&mut world, i, i as f32);
+            if i % 3 == 0 {
+                world.entity_mut(entity).insert(TestComponent { value: 0 });
+            }
+            if i % 5 == 0 {
+                let child = spawn_tracked(&mut world, 1000 + i, 0.);
+                world.entity_mut(entity).push_children(&[child]);
+            }
+        }
+
+        let serial = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+        let parallel =
+            WorldSnapshot::<TestSnap>::from_world_parallel(&world, &registry, &TaskPool::new());
+        assert_eq!(serial.entities.len(), 120);
+        assert_eq!(
+            format!("{:?}", serial.entities),
+            format!("{:?}", parallel.entities)
+        );
+        for (serial, parallel) in serial.entities.iter().zip(parallel.entities.iter()) {
+            assert_eq!(serial.components.len(), parallel.components.len());
+            for (a, b) in serial.components.iter().zip(parallel.components.iter()) {
+                assert_eq!(a.reflect_partial_eq(&**b), Some(true));
+            }
+        }
+        assert_eq!(serial.checksum, parallel.checksum);
+    }
+
+    #[test]
+    fn snap_clone_fast_path() {
+        #[derive(Component, Reflect, Default, Clone)]
+        #[reflect(Component, SnapClone)]
+        struct Health(u32);
+
+        let registry = test_registry();
+        registry.write().register::<Health>();
+        register_snap_clone::<Transform>(&mut registry.write());
+
+        let mut world = World::default();
+        let a = spawn_tracked(&mut world, 0, 1.);
+        world.entity_mut(a).insert(Health(10));
+        let b = spawn_tracked(&mut world, 1, 2.);
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+        assert!(snapshot.entities.iter().all(|e| e.components.is_empty()));
+
+        world.get_mut::<Health>(a).unwrap().0 = 0;
+        world.get_mut::<Transform>(b).unwrap().translation.x = 5.;
+        world.entity_mut(b).insert(Health(3));
+        let report = snapshot
+            .write_to_world(
+                &mut world,
+                registry.clone(),
+                LoadMode::Replace,
+                &LoadFilter::all(),
+            )
+            .unwrap();
+        assert_eq!(world.get::<Health>(a).unwrap().0, 10);
+        assert_eq!(world.get::<Transform>(b).unwrap().translation.x, 2.);
+        assert!(world.get::<Health>(b).is_none());
+        assert_eq!(report.components_removed.len(), 1);
+
+        // the same values can be loaded through reflection
+        let mut reflective = snapshot.clone();
+        reflective.make_reflective();
+        assert!(reflective.columns.is_empty());
+        let mut world = World::default();
+        reflective
+            .write_to_world(&mut world, registry, LoadMode::Replace, &LoadFilter::all())
+            .unwrap();
+        let mut query = world.query::<(&SnapshotId<TestSnap>, &Transform)>();
+        assert_eq!(query.iter(&world).count(), 2);
+        let mut query = world.query::<&Health>();
+        assert_eq!(
+            query
+                .iter(&world)
+                .map(|health| health.0)
+                .collect::<Vec<_>>(),
+            [10]
+        );
+    }
+
+    #[test]
+    fn excludes_and_pins_components() {
+        let mut world = World::default();
+        let registry = test_registry();
+        register_snap_markers::<TestSnap, Transform>(&mut registry.write());
+        let excluded = spawn_tracked(&mut world, 0, 1.);
+        world
+            .entity_mut(excluded)
+            .insert(SnapExclude::<TestSnap, Transform>::default());
+        let pinned = spawn_tracked(&mut world, 1, 2.);
+        world
+            .entity_mut(pinned)
+            .insert(SnapPinned::<TestSnap, Transform>::default());
+        let other = spawn_tracked(&mut world, 2, 3.);
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+
+        let saved = |entity| {
+            snapshot
+                .entities
+                .iter()
+                .find(|snapshot_entity| snapshot_entity.entity == entity)
+                .unwrap()
+                .components
+                .len()
+        };
+        assert_eq!(saved(excluded), 0);
+        assert_eq!(saved(pinned), 1);
+
+        for entity in [excluded, pinned, other] {
+            world.get_mut::<Transform>(entity).unwrap().translation.x = 10.;
+        }
+        snapshot
+            .write_to_world(&mut world, registry, LoadMode::Replace, &LoadFilter::all())
+            .unwrap();
+        assert_eq!(world.get::<Transform>(excluded).unwrap().translation.x, 10.);
+        assert_eq!(world.get::<Transform>(pinned).unwrap().translation.x, 10.);
+        assert_eq!(world.get::<Transform>(other).unwrap().translation.x, 3.);
+    }
+
+    #[test]
+    fn skips_transient_fields() {
+        #[derive(Component, Reflect, Default)]
+        #[reflect(Component, SnapTransient)]
+        struct Effect {
+            strength: f32,
+            timer: f32,
+        }
+
+        impl SnapTransientFields for Effect {
+            const TRANSIENT_FIELDS: &'static [&'static str] = &["timer"];
+        }
+
+        #[derive(Component, Reflect, Default)]
+        #[reflect(Component, SnapTransient)]
+        struct Glow {
+            strength: f32,
+            timer: f32,
+        }
+
+        impl SnapTransientFields for Glow {
+            const TRANSIENT_FIELDS: &'static [&'static str] = &["timer"];
+            const RESET_ON_LOAD: bool = true;
+        }
+
+        let registry = test_registry();
+        registry.write().register::<Effect>();
+        registry.write().register::<Glow>();
+        let mut world = World::default();
+        let entity = spawn_tracked(&mut world, 0, 0.);
+        world
+            .entity_mut(entity)
+            .insert(Effect {
+                strength: 1.,
+                timer: 1.,
+            })
+            .insert(Glow {
+                strength: 1.,
+                timer: 1.,
+            });
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+
+        let saved = snapshot.entities[0]
+            .components
+            .iter()
+            .find(|component| component.type_name() == std::any::type_name::<Effect>())
+            .unwrap();
+        match saved.reflect_ref() {
+            bevy::reflect::ReflectRef::Struct(saved) => {
+                assert!(saved.field("strength").is_some());
+                assert!(saved.field("timer").is_none());
+            }
+            _ => panic!("expected a struct"),
+        }
+
+        let mut effect = world.get_mut::<Effect>(entity).unwrap();
+        effect.strength = 2.;
+        effect.timer = 2.;
+        let mut glow = world.get_mut::<Glow>(entity).unwrap();
+        glow.strength = 2.;
+        glow.timer = 2.;
+        snapshot
+            .write_to_world(&mut world, registry, LoadMode::Replace, &LoadFilter::all())
+            .unwrap();
+        let effect = world.get::<Effect>(entity).unwrap();
+        assert_eq!((effect.strength, effect.timer), (1., 2.));
+        let glow = world.get::<Glow>(entity).unwrap();
+        assert_eq!((glow.strength, glow.timer), (1., 0.));
+    }
+
+    #[test]
+    fn hashes_transient_types_without_transient_fields() {
+        #[derive(Component, Reflect, Default, Hash)]
+        #[reflect(Component, Hash, SnapTransient)]
+        struct Counter {
+            value: u32,
+            ticks: u32,
+        }
+
+        impl SnapTransientFields for Counter {
+            const TRANSIENT_FIELDS: &'static [&'static str] = &["ticks"];
+        }
+
+        let registry = test_registry();
+        registry.write().register::<Counter>();
+        let mut world = World::default();
+        let entity = spawn_tracked(&mut world, 0, 0.);
+        let without = WorldSnapshot::<TestSnap>::from_world(&world, &registry).checksum;
+        world
+            .entity_mut(entity)
+            .insert(Counter { value: 1, ticks: 1 });
+        let checksum = WorldSnapshot::<TestSnap>::from_world(&world, &registry).checksum;
+        assert_ne!(checksum, without);
+
+        world.get_mut::<Counter>(entity).unwrap().ticks = 2;
+        let ticked = WorldSnapshot::<TestSnap>::from_world(&world, &registry).checksum;
+        assert_eq!(ticked, checksum);
+
+        world.get_mut::<Counter>(entity).unwrap().value = 2;
+        let changed = WorldSnapshot::<TestSnap>::from_world(&world, &registry).checksum;
+        assert_ne!(changed, checksum);
+    }
+
+    #[test]
+    fn rejects_unknown_transient_fields() {
+        #[derive(Component, Reflect, Default)]
+        #[reflect(Component, SnapTransient)]
+        struct Effect {
+            strength: f32,
+        }
+
+        impl SnapTransientFields for Effect {
+            const TRANSIENT_FIELDS: &'static [&'static str] = &["timer"];
+        }
+
+        let mut registry = SnapRegistry::<TestSnap>::default();
+        assert_eq!(
+            registry.register::<Effect>(),
+            Err(SnapError::UnknownTransientField {
+                type_name: std::any::type_name::<Effect>().to_string(),
+                field: "timer".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn unloads_and_loads_chunks() {
+        let mut app = App::new();
+        app.add_plugin(SnapPlugin::<TestSnap>::default());
+        let world = &mut app.world;
+        let mut chunks = SnapChunks::<TestSnap, i32>::new(|world, entity| {
+            let transform = world.get::<Transform>(entity)?;
+            Some((transform.translation.x / 10.).floor() as i32)
+        });
+        for x in [1., 5., 15.] {
+            let id = world.resource_mut::<SnapshotIdProvider<TestSnap>>().next();
+            world
+                .spawn()
+                .insert(Transform::from_xyz(x, 0., 0.))
+                .insert(id);
+        }
+
+        chunks.unload(world, 0).unwrap();
+        let mut query = world.query::<(&SnapshotId<TestSnap>, &Transform)>();
+        assert_eq!(query.iter(world).count(), 1);
+        assert_eq!(chunks.get(&0).unwrap().entities.len(), 2);
+
+        // ids of unloaded entities are not handed out again
+        let id = world.resource_mut::<SnapshotIdProvider<TestSnap>>().next();
+        assert_eq!(id.id(), &SnapshotKey::Index(3));
+
+        let report = chunks.load(world, &0).unwrap().unwrap();
+        assert_eq!(report.spawned.len(), 2);
+        assert!(chunks.get(&0).is_none());
+        let mut loaded = query
+            .iter(world)
+            .map(|(id, transform)| (id.id().clone(), transform.translation.x))
+            .collect::<Vec<_>>();
+        loaded.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        assert_eq!(
+            loaded,
+            [
+                (SnapshotKey::Index(0), 1.),
+                (SnapshotKey::Index(1), 5.),
+                (SnapshotKey::Index(2), 15.)
+            ]
+        );
+        assert!(chunks.load(world, &0).unwrap().is_none());
+    }
+
+    #[test]
+    fn unloads_and_loads_chunks_across_hierarchy() {
+        let mut app = App::new();
+        app.add_plugin(SnapPlugin::<TestSnap>::default());
+        let world = &mut app.world;
+        let mut chunks = SnapChunks::<TestSnap, i32>::new(|world, entity| {
+            let transform = world.get::<Transform>(entity)?;
+            Some((transform.translation.x / 10.).floor() as i32)
+        });
+        let parent = spawn_tracked(world, 0, 1.);
+        let child = spawn_tracked(world, 1, 15.);
+        world.entity_mut(parent).push_children(&[child]);
+
+        // unloading the parent's chunk leaves the child of the other chunk in the world
+        chunks.unload(world, 0).unwrap();
+        assert!(world.get_entity(parent).is_none());
+        assert!(world.get::<Parent>(child).is_none());
+        chunks.load(world, &0).unwrap().unwrap();
+
+        // and the child's chunk can be loaded without its parent
+        let parent = world
+            .query::<(Entity, &SnapshotId<TestSnap>)>()
+            .iter(world)
+            .find(|(_, id)| id.id() == &SnapshotKey::Index(0))
+            .unwrap()
+            .0;
+        world.entity_mut(parent).push_children(&[child]);
+        chunks.unload(world, 1).unwrap();
+        assert!(world.get_entity(child).is_none());
+        assert!(world.get::<Children>(parent).unwrap().is_empty());
+        let report = chunks.load(world, &1).unwrap().unwrap();
+        assert_eq!(report.spawned, vec![SnapshotKey::Index(1)]);
+    }
+
+    #[test]
+    fn registers_types_at_runtime() {
+        #[derive(Component, Reflect, Default)]
+        #[reflect(Component)]
+        struct Score(u32);
+
+        #[derive(Reflect, Default)]
+        struct NotAComponent;
+
+        let mut app = App::new();
+        app.register_type::<Score>()
+            .register_type::<NotAComponent>()
+            .add_plugin(SnapPlugin::<TestSnap>::default().with_app_types(["Score"]));
+        app.update();
+        let world = &mut app.world;
+        let is_registered = |world: &World, name: &str| {
+            let registry = world.resource::<SnapRegistry<TestSnap>>();
+            let type_registry = registry.type_registry().read();
+            type_registry.get_with_short_name(name).is_some()
+        };
+        assert!(is_registered(world, "Score"));
+
+        let app_registry = world.resource::<TypeRegistry>().clone();
+        let mut registry = world.resource_mut::<SnapRegistry<TestSnap>>();
+        assert_eq!(
+            registry.register_names(&app_registry.read(), ["Score", "Missing"]),
+            Err(SnapError::UnknownType("Missing".to_string()))
+        );
+        assert!(matches!(
+            registry.register_by_name(&app_registry.read(), "NotAComponent"),
+            Err(SnapError::MissingTypeData(_))
+        ));
+        assert!(registry.unregister::<Transform>());
+        assert!(!registry.unregister::<Transform>());
+        assert!(!is_registered(world, "Transform"));
+        assert!(is_registered(world, "Score"));
+    }
+
+    #[test]
+    fn derives_snap_type() {
+        // no `#[reflect(...)]`, the derive adds the type data
+        #[derive(Component, Reflect, Default)]
+        struct Health(u32);
+
+        #[derive(Component, Reflect, Default)]
+        struct Score(u32);
+
+        #[derive(Default, SnapType)]
+        #[snap(components(Transform, Health))]
+        #[snap(resources(Score))]
+        struct DerivedSnap;
+
+        let mut app = App::new();
+        app.add_plugin(SnapPlugin::<DerivedSnap>::default());
+        let world = &mut app.world;
+        world.insert_resource(Score(3));
+        let entity = world
+            .spawn()
+            .insert(Health(10))
+            .insert(SnapshotId::<DerivedSnap>::new(0))
+            .id();
+        let snapshot = world.save_snapshot::<DerivedSnap>().unwrap();
+
+        world.get_mut::<Health>(entity).unwrap().0 = 1;
+        world.resource_mut::<Score>().0 = 0;
+        world.load_snapshot(&snapshot).unwrap();
+        assert_eq!(world.get::<Health>(entity).unwrap().0, 10);
+        assert_eq!(world.resource::<Score>().0, 3);
+    }
+
+    #[test]
+    fn saves_handles_by_asset_path() {
+        #[derive(TypeUuid)]
+        #[uuid = "8b4c6f0e-2a5d-4f1e-9c3b-7d2e1a0f5b6c"]
+        struct TestAsset;
+
+        #[derive(Default)]
+        struct HandleSnap;
+
+        impl SnapType for HandleSnap {
+            fn add_types(registry: &mut TypeRegistry) {
+                register_asset_handle::<TestAsset>(&mut registry.write());
+            }
+        }
+
+        let mut app = App::new();
+        app.add_plugin(SnapPlugin::<HandleSnap>::default());
+        let world = &mut app.world;
+        let asset_server = AssetServer::new(FileAssetIo::new("assets", false), TaskPool::new());
+        let handle: Handle<TestAsset> = asset_server.load("levels/one.test#spawn");
+        world.insert_resource(asset_server);
+        let entity = world
+            .spawn()
+            .insert(handle.clone())
+            .insert(SnapshotId::<HandleSnap>::new(0))
+            .id();
+        let snapshot = world.save_snapshot::<HandleSnap>().unwrap();
+
+        world.despawn(entity);
+        world.load_snapshot(&snapshot).unwrap();
+        let restored = world
+            .query::<&Handle<TestAsset>>()
+            .iter(world)
+            .next()
+            .unwrap();
+        assert_eq!(restored.id, handle.id);
+        assert!(restored.is_strong());
+
+        // assets created at runtime have no path
+        let runtime = Handle::<TestAsset>::weak(HandleId::random::<TestAsset>());
+        let entity = world
+            .spawn()
+            .insert(runtime)
+            .insert(SnapshotId::<HandleSnap>::new(1))
+            .id();
+        assert_eq!(
+            world.save_snapshot::<HandleSnap>().err(),
+            Some(SnapError::MissingAssetPath {
+                handle: std::any::type_name::<Handle<TestAsset>>().to_string(),
+                entity,
+            })
+        );
+    }
+
+    #[test]
+    fn warns_about_nested_handles() {
+        #[derive(TypeUuid)]
+        #[uuid = "c2e7a41b-5d90-4f3a-8b6e-1f4d2c7a9e05"]
+        struct TestAsset;
+
+        #[derive(Component, Reflect, Default)]
+        #[reflect(Component)]
+        struct Sprite {
+            image: Handle<TestAsset>,
+        }
+
+        #[derive(Default)]
+        struct NestedSnap;
+
+        impl SnapType for NestedSnap {
+            fn add_types(registry: &mut TypeRegistry) {
+                registry.write().register::<Sprite>();
+                registry.write().register::<Transform>();
+            }
+        }
+
+        let mut world = World::default();
+        let registry = SnapRegistry::<NestedSnap>::default().type_registry;
+        world
+            .spawn()
+            .insert(Sprite::default())
+            .insert(Transform::default())
+            .insert(SnapshotId::<NestedSnap>::new(0));
+        let snapshot = WorldSnapshot::<NestedSnap>::from_world(&world, &registry);
+        let report = snapshot
+            .write_to_world(&mut world, registry, LoadMode::Replace, &LoadFilter::all())
+            .unwrap();
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains(std::any::type_name::<Sprite>()));
+    }
+
+    #[test]
+    fn embeds_runtime_assets() {
+        #[derive(TypeUuid, Clone)]
+        #[uuid = "3f1a9d2c-6b7e-4c8f-a5d0-e2b4c6a8f0d1"]
+        struct Generated(u32);
+
+        #[derive(Default)]
+        struct EmbedSnap;
+
+        impl SnapType for EmbedSnap {
+            fn add_types(registry: &mut TypeRegistry) {
+                register_embedded_asset::<Generated>(&mut registry.write());
+            }
+        }
+
+        let mut app = App::new();
+        app.insert_resource(IoTaskPool(TaskPool::new()))
+            .add_plugin(AssetPlugin)
+            .add_asset::<Generated>()
+            .add_plugin(SnapPlugin::<EmbedSnap>::default());
+        let world = &mut app.world;
+        let handle = world.resource_mut::<Assets<Generated>>().add(Generated(7));
+        for id in 0..2 {
+            world
+                .spawn()
+                .insert(handle.clone())
+                .insert(SnapshotId::<EmbedSnap>::new(id));
+        }
+        let snapshot = world.save_snapshot::<EmbedSnap>().unwrap();
+        // both handles point to the same asset
+        assert_eq!(snapshot.assets.len(), 1);
+
+        world.load_snapshot(&snapshot).unwrap();
+        let handles = world
+            .query::<&Handle<Generated>>()
+            .iter(world)
+            .map(|handle| handle.id)
+            .collect::<Vec<_>>();
+        assert_eq!(handles.len(), 2);
+        assert_eq!(handles[0], handles[1]);
+        assert_ne!(handles[0], handle.id);
+        let assets = world.resource::<Assets<Generated>>();
+        assert_eq!(assets.get(handles[0]).unwrap().0, 7);
+    }
+
+    #[test]
+    fn resources_without_component() {
+        #[derive(Reflect, Default)]
+        #[reflect(Resource)]
+        struct Steps(u32);
+
+        // neither Default nor FromWorld
+        #[derive(Reflect, FromReflect)]
+        struct Seed(u64);
+
+        #[derive(Default)]
+        struct ResourceSnap;
+
+        impl SnapType for ResourceSnap {
+            fn add_types(registry: &mut TypeRegistry) {
+                let mut registry = registry.write();
+                registry.register::<Steps>();
+                register_resource_from_reflect::<Seed>(&mut registry);
+            }
+        }
+
+        let mut app = App::new();
+        app.add_plugin(SnapPlugin::<ResourceSnap>::default());
+        let world = &mut app.world;
+        world.insert_resource(Steps(3));
+        world.insert_resource(Seed(42));
+        let snapshot = world.save_snapshot::<ResourceSnap>().unwrap();
+
+        world.remove_resource::<Steps>();
+        world.remove_resource::<Seed>();
+        let report = world.load_snapshot(&snapshot).unwrap();
+        assert_eq!(report.resources_inserted.len(), 2);
+        assert_eq!(world.resource::<Steps>().0, 3);
+        assert_eq!(world.resource::<Seed>().0, 42);
+    }
+
+    #[test]
+    fn picks_resource_constructors() {
+        #[derive(Reflect, Default)]
+        struct Steps(u32);
+
+        // neither Default nor FromWorld
+        #[derive(Reflect, FromReflect)]
+        struct Seed(u64);
+
+        // no constructor the derive can use
+        #[derive(Reflect)]
+        struct Opaque(u32);
+
+        #[derive(Default, SnapType)]
+        #[snap(resources(Steps, Seed))]
+        struct DerivedSnap;
+
+        #[derive(Default, SnapType)]
+        #[snap(resources(Opaque))]
+        struct OpaqueSnap;
+
+        let mut app = App::new();
+        app.add_plugin(SnapPlugin::<DerivedSnap>::default())
+            .add_plugin(SnapPlugin::<OpaqueSnap>::default());
+        let world = &mut app.world;
+        world.insert_resource(Steps(3));
+        world.insert_resource(Seed(42));
+        let snapshot = world.save_snapshot::<DerivedSnap>().unwrap();
+        world.remove_resource::<Steps>();
+        world.remove_resource::<Seed>();
+        world.load_snapshot(&snapshot).unwrap();
+        assert_eq!(world.resource::<Steps>().0, 3);
+        assert_eq!(world.resource::<Seed>().0, 42);
+
+        world.insert_resource(Opaque(1));
+        assert_eq!(
+            world.save_snapshot::<OpaqueSnap>().err(),
+            Some(SnapError::MissingConstructor(
+                std::any::type_name::<Opaque>().to_string()
+            ))
+        );
+
+        // resources without a constructor of their own start out from their ReflectDefault
+        #[derive(Reflect, Default)]
+        #[reflect(Default)]
+        struct Level(u32);
+
+        let mut registration = Level::get_type_registration();
+        registration.insert(ReflectResource::without_constructor::<Level>());
+        world
+            .resource_mut::<SnapRegistry<DerivedSnap>>()
+            .add_registration(registration)
+            .unwrap();
+        world.insert_resource(Level(7));
+        let snapshot = world.save_snapshot::<DerivedSnap>().unwrap();
+        world.remove_resource::<Level>();
+        world.load_snapshot(&snapshot).unwrap();
+        assert_eq!(world.resource::<Level>().0, 7);
+    }
+
+    #[test]
+    fn checks_resources_before_writing_entities() {
+        #[derive(Reflect, FromReflect)]
+        struct Seed(u64);
+
+        #[derive(Default)]
+        struct SeedSnap;
+
+        impl SnapType for SeedSnap {
+            fn add_types(registry: &mut TypeRegistry) {
+                let mut registry = registry.write();
+                registry.register::<Transform>();
+                register_resource_from_reflect::<Seed>(&mut registry);
+            }
+        }
+
+        let mut app = App::new();
+        app.add_plugin(SnapPlugin::<SeedSnap>::default());
+        let world = &mut app.world;
+        let entity = world
+            .spawn()
+            .insert(SnapshotId::<SeedSnap>::new(0))
+            .insert(Transform::from_xyz(1., 0., 0.))
+            .id();
+        let mut snapshot = world.save_snapshot::<SeedSnap>().unwrap();
+        // a saved seed without its value can't be turned back into a `Seed`
+        let mut seed = DynamicTupleStruct::default();
+        seed.set_name(std::any::type_name::<Seed>().to_string());
+        snapshot.resources.push(Box::new(seed));
+
+        world.get_mut::<Transform>(entity).unwrap().translation.x = 2.;
+        assert!(matches!(
+            world.load_snapshot(&snapshot),
+            Err(SnapError::TypeMismatch { .. })
+        ));
+        assert_eq!(world.get::<Transform>(entity).unwrap().translation.x, 2.);
+    }
+
+    #[test]
+    fn copies_tracked_state_between_worlds() {
+        #[derive(Reflect, Default)]
+        #[reflect(Resource)]
+        struct Score(u32);
+
+        #[derive(Default)]
+        struct CopySnap;
+
+        impl SnapType for CopySnap {
+            fn add_types(registry: &mut TypeRegistry) {
+                let mut registry = registry.write();
+                registry.register::<Transform>();
+                registry.register::<Score>();
+            }
+        }
+
+        let mut app = App::new();
+        app.add_plugin(SnapPlugin::<CopySnap>::default());
+        let source = &mut app.world;
+        source.insert_resource(Score(5));
+        let child = source
+            .spawn()
+            .insert_bundle(TransformBundle::from_transform(Transform::from_xyz(
+                1., 0., 0.,
+            )))
+            .insert(SnapshotId::<CopySnap>::new(1))
+            .id();
+        source
+            .spawn()
+            .insert_bundle(TransformBundle::from_transform(Transform::from_xyz(
+                10., 0., 0.,
+            )))
+            .insert(SnapshotId::<CopySnap>::new(0))
+            .push_children(&[child]);
+
+        let mut destination = World::default();
+        let stale = destination
+            .spawn()
+            .insert(SnapshotId::<CopySnap>::new(2))
+            .id();
+        let report = source
+            .copy_tracked_to::<CopySnap>(&mut destination)
+            .unwrap();
+        assert_eq!(report.spawned.len(), 2);
+        assert_eq!(report.despawned, vec![(SnapshotKey::Index(2), stale)]);
+        assert!(destination.get_entity(stale).is_none());
+        assert_eq!(destination.resource::<Score>().0, 5);
+
+        let copied_child = report.entities[&SnapshotKey::Index(1)].new;
+        let copied_parent = report.entities[&SnapshotKey::Index(0)].new;
+        assert_eq!(
+            destination.get::<Parent>(copied_child).unwrap().0,
+            copied_parent
+        );
+        assert_eq!(
+            destination
+                .get::<Transform>(copied_child)
+                .unwrap()
+                .translation
+                .x,
+            1.
+        );
+
+        // copying again updates the same entities
+        source.resource_mut::<Score>().0 = 6;
+        source.entity_mut(child).remove::<Transform>();
+        let report = source
+            .copy_tracked_to::<CopySnap>(&mut destination)
+            .unwrap();
+        assert_eq!(report.updated.len(), 2);
+        assert_eq!(
+            report.components_removed,
+            vec![(copied_child, Transform::default().type_name())]
+        );
+        assert_eq!(destination.resource::<Score>().0, 6);
+    }
+
+    #[test]
+    fn snapshots_states_and_events() {
+        #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+        enum GameState {
+            Menu,
+            Playing,
+            Paused,
+        }
+
+        #[derive(Clone, PartialEq, Debug)]
+        struct Scored(u32);
+
+        #[derive(Default)]
+        struct Entered(u32);
+
+        #[derive(Default, SnapType)]
+        #[snap(states(GameState), events(Scored))]
+        struct FlowSnap;
+
+        let mut app = App::new();
+        app.add_plugin(SnapPlugin::<FlowSnap>::default())
+            .add_event::<Scored>()
+            .init_resource::<Entered>()
+            .add_state(GameState::Menu)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(|mut entered: ResMut<Entered>| entered.0 += 1),
+            );
+        app.update();
+        app.world
+            .resource_mut::<State<GameState>>()
+            .push(GameState::Playing)
+            .unwrap();
+        app.update();
+        app.world
+            .resource_mut::<State<GameState>>()
+            .push(GameState::Paused)
+            .unwrap();
+        app.update();
+        assert_eq!(app.world.resource::<Entered>().0, 1);
+        app.world.resource_mut::<Events<Scored>>().send(Scored(1));
+
+        let snapshot = app.world.save_snapshot::<FlowSnap>().unwrap();
+
+        app.world
+            .resource_mut::<State<GameState>>()
+            .replace(GameState::Menu)
+            .unwrap();
+        app.update();
+        app.world.resource_mut::<Events<Scored>>().clear();
+        app.world.load_snapshot(&snapshot).unwrap();
+        app.update();
+
+        let state = app.world.resource::<State<GameState>>();
+        assert_eq!(state.current(), &GameState::Paused);
+        assert_eq!(state.inactives(), &[GameState::Menu, GameState::Playing]);
+        // restoring the stack doesn't enter `Playing` again
+        assert_eq!(app.world.resource::<Entered>().0, 1);
+        let events = app.world.resource::<Events<Scored>>();
+        assert_eq!(
+            events.get_reader().iter(events).collect::<Vec<_>>(),
+            vec![&Scored(1)]
+        );
+
+        // a snapshot of a world without the state removes it
+        let mut world = World::default();
+        world.insert_resource(SnapRegistry::<FlowSnap>::default());
+        let empty = world.save_snapshot::<FlowSnap>().unwrap();
+        let report = app.world.load_snapshot(&empty).unwrap();
+        assert!(!app.world.contains_resource::<State<GameState>>());
+        assert!(report
+            .resources_removed
+            .contains(&std::any::type_name::<State<GameState>>()));
+    }
+
+    #[test]
+    fn matches_named_entities() {
+        let mut world = World::default();
+        let registry = test_registry();
+
+        let door_id = SnapshotId::<TestSnap>::from_path(["level", "room", "door"]);
+        let door = world
+            .spawn()
+            .insert(Transform::from_xyz(1., 0., 0.))
+            .insert(door_id.child("handle"))
+            .id();
+        world
+            .spawn()
+            .insert(Transform::from_xyz(2., 0., 0.))
+            .insert(SnapshotId::<TestSnap>::named("chest"));
+        let snapshot = WorldSnapshot::<TestSnap>::from_world(&world, &registry);
+
+        world.get_mut::<Transform>(door).unwrap().translation.x = 10.;
+        snapshot
+            .write_to_world(&mut world, registry, LoadMode::Replace, &LoadFilter::all())
+            .unwrap();
+        assert_eq!(world.get::<Transform>(door).unwrap().translation.x, 1.);
+
+        assert_eq!(
+            SnapshotKey::from_path(["level", "room", "door"]),
+            *door_id.id()
+        );
+        assert_ne!(SnapshotKey::from_path(["level", "roomdoor"]), *door_id.id());
+        match door_id.id() {
+            SnapshotKey::Uuid(uuid) => assert_eq!(uuid.get_version_num(), 5),
+            key => panic!("expected a UUID key, got {:?}", key),
+        }
+    }
 }
@@ -0,0 +1,103 @@
+use std::any::TypeId;
+
+use bevy::{ecs::component::Component, prelude::default, utils::HashSet};
+
+use crate::SnapshotKey;
+
+/// Selects which parts of a snapshot are applied when loading. Everything else in the world is
+/// left untouched, i.e. unselected entities are never despawned.
+///
+/// The default filter selects everything. Start from `LoadFilter::none()` and add what you
+/// want to restore, e.g. `LoadFilter::none().with_entity("player").with_component::<Inventory>()`
+/// only restores the inventory of the player.
+///
+/// Parent-child relationships are only restored when all component types are selected, and only
+/// between selected entities. Links to unselected entities are kept as they are.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadFilter {
+    entities: Option<HashSet<SnapshotKey>>,
+    components: Option<HashSet<TypeId>>,
+    resources: Option<HashSet<TypeId>>,
+}
+
+impl LoadFilter {
+    /// Selects every entity, component and resource in the snapshot.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Selects nothing.
+    pub fn none() -> Self {
+        Self {
+            entities: Some(default()),
+            components: Some(default()),
+            resources: Some(default()),
+        }
+    }
+
+    /// Selects the entity with the given snapshot id.
+    pub fn with_entity(mut self, id: impl Into<SnapshotKey>) -> Self {
+        self.entities.get_or_insert_with(default).insert(id.into());
+        self
+    }
+
+    /// Selects all entities.
+    pub fn with_all_entities(mut self) -> Self {
+        self.entities = None;
+        self
+    }
+
+    /// Selects the component type `C` on all selected entities.
+    pub fn with_component<C: Component>(mut self) -> Self {
+        self.components
+            .get_or_insert_with(default)
+            .insert(TypeId::of::<C>());
+        self
+    }
+
+    /// Selects all component types.
+    pub fn with_all_components(mut self) -> Self {
+        self.components = None;
+        self
+    }
+
+    /// Selects the resource type `R`.
+    pub fn with_resource<R: Send + Sync + 'static>(mut self) -> Self {
+        self.resources
+            .get_or_insert_with(default)
+            .insert(TypeId::of::<R>());
+        self
+    }
+
+    /// Selects all resource types.
+    pub fn with_all_resources(mut self) -> Self {
+        self.resources = None;
+        self
+    }
+
+    pub fn includes_entity(&self, id: &SnapshotKey) -> bool {
+        self.entities
+            .as_ref()
+            .map_or(true, |entities| entities.contains(id))
+    }
+
+    pub fn includes_component(&self, type_id: TypeId) -> bool {
+        self.components
+            .as_ref()
+            .map_or(true, |components| components.contains(&type_id))
+    }
+
+    pub fn includes_resource(&self, type_id: TypeId) -> bool {
+        self.resources
+            .as_ref()
+            .map_or(true, |resources| resources.contains(&type_id))
+    }
+
+    pub(crate) fn includes_all_components(&self) -> bool {
+        self.components.is_none()
+    }
+
+    pub(crate) fn includes_everything(&self) -> bool {
+        *self == Self::all()
+    }
+}
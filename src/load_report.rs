@@ -0,0 +1,57 @@
+use std::marker::PhantomData;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{SnapType, SnapshotKey};
+
+/// The entity a snapshot entity had when the snapshot was taken, and the entity it was written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityMapping {
+    pub old: Entity,
+    pub new: Entity,
+}
+
+/// Describes what changed in the world when a snapshot was loaded.
+#[derive(Clone, Debug, Default)]
+pub struct LoadReport {
+    /// Entities that were spawned, because there was no entity with their snapshot id.
+    pub spawned: Vec<SnapshotKey>,
+    /// Entities that already existed and were updated.
+    pub updated: Vec<SnapshotKey>,
+    /// Tagged entities that were despawned, because they were not in the snapshot.
+    pub despawned: Vec<(SnapshotKey, Entity)>,
+    /// Snapshot entities mapped to the entities they were written to.
+    pub entities: HashMap<SnapshotKey, EntityMapping>,
+    /// Components that were added to entities, by type name.
+    pub components_added: Vec<(Entity, &'static str)>,
+    /// Components that were removed from entities, by type name.
+    pub components_removed: Vec<(Entity, &'static str)>,
+    /// Resources that were inserted, by type name.
+    pub resources_inserted: Vec<&'static str>,
+    /// Resources that were removed, by type name.
+    pub resources_removed: Vec<&'static str>,
+    /// Problems that didn't prevent the snapshot from loading.
+    pub warnings: Vec<String>,
+}
+
+impl LoadReport {
+    pub(crate) fn warn(&mut self, warning: String) {
+        warn!("{}", warning);
+        self.warnings.push(warning);
+    }
+}
+
+/// Sent after a `LoadCommand` has written a snapshot to the world.
+pub struct LoadEvent<T: SnapType> {
+    pub report: LoadReport,
+    t: PhantomData<T>,
+}
+
+impl<T: SnapType> From<LoadReport> for LoadEvent<T> {
+    fn from(report: LoadReport) -> Self {
+        Self {
+            report,
+            t: default(),
+        }
+    }
+}
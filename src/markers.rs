@@ -0,0 +1,123 @@
+use std::{any::TypeId, marker::PhantomData};
+
+use bevy::{
+    ecs::component::{Component, ComponentId, TableStorage},
+    prelude::*,
+    reflect::{GetTypeRegistration, TypeRegistryInternal},
+    utils::HashMap,
+};
+
+use crate::SnapType;
+
+/// Don't save the `C` of this entity in `T` snapshots, and leave it untouched when loading them.
+///
+/// `C` has to be registered with `register_snap_markers`.
+pub struct SnapExclude<T, C> {
+    t: PhantomData<(T, C)>,
+}
+
+/// Save the `C` of this entity in `T` snapshots, but never overwrite or remove it when loading
+/// them, e.g. for the camera's `Transform` while rewinding.
+///
+/// `C` has to be registered with `register_snap_markers`.
+pub struct SnapPinned<T, C> {
+    t: PhantomData<(T, C)>,
+}
+
+impl<T: SnapType, C: Component> Component for SnapExclude<T, C> {
+    type Storage = TableStorage;
+}
+
+impl<T: SnapType, C: Component> Component for SnapPinned<T, C> {
+    type Storage = TableStorage;
+}
+
+impl<T, C> Default for SnapExclude<T, C> {
+    fn default() -> Self {
+        Self { t: PhantomData }
+    }
+}
+
+impl<T, C> Default for SnapPinned<T, C> {
+    fn default() -> Self {
+        Self { t: PhantomData }
+    }
+}
+
+/// The markers of a type registered with `register_snap_markers`.
+#[derive(Clone)]
+pub(crate) struct ReflectSnapMarkers {
+    excluded: TypeId,
+    pinned: TypeId,
+}
+
+/// Registers `C` as a type whose `SnapExclude<T, C>` and `SnapPinned<T, C>` markers are honored by
+/// `T` snapshots. Markers of types that weren't registered this way have no effect.
+pub fn register_snap_markers<T, C>(registry: &mut TypeRegistryInternal)
+where
+    T: SnapType,
+    C: Component + GetTypeRegistration,
+{
+    if registry.get(TypeId::of::<C>()).is_none() {
+        registry.register::<C>();
+    }
+    let registration = registry.get_mut(TypeId::of::<C>()).unwrap();
+    registration.insert(ReflectSnapMarkers {
+        excluded: TypeId::of::<SnapExclude<T, C>>(),
+        pinned: TypeId::of::<SnapPinned<T, C>>(),
+    });
+}
+
+/// The `SnapExclude` and `SnapPinned` markers in use in a world, by the type they mark.
+#[derive(Default)]
+pub(crate) struct SnapMarkers {
+    excluded: HashMap<TypeId, ComponentId>,
+    pinned: HashMap<TypeId, ComponentId>,
+}
+
+impl SnapMarkers {
+    /// Finds the markers of the types registered with `register_snap_markers` that the world has
+    /// seen so far.
+    pub fn new(world: &World, type_registry: &TypeRegistryInternal) -> Self {
+        let mut markers = SnapMarkers::default();
+        let components = world.components();
+        for registration in type_registry.iter() {
+            let marked = match registration.data::<ReflectSnapMarkers>() {
+                Some(marked) => marked,
+                None => continue,
+            };
+            if let Some(component_id) = components.get_id(marked.excluded) {
+                markers
+                    .excluded
+                    .insert(registration.type_id(), component_id);
+            }
+            if let Some(component_id) = components.get_id(marked.pinned) {
+                markers.pinned.insert(registration.type_id(), component_id);
+            }
+        }
+        markers
+    }
+
+    /// The marker of entities whose `type_id` component must not be saved.
+    pub fn excluded(&self, type_id: TypeId) -> Option<ComponentId> {
+        self.excluded.get(&type_id).copied()
+    }
+
+    /// The markers of entities whose `type_id` component must not be written to.
+    pub fn protected(&self, type_id: TypeId) -> Vec<ComponentId> {
+        self.excluded
+            .get(&type_id)
+            .into_iter()
+            .chain(self.pinned.get(&type_id))
+            .copied()
+            .collect()
+    }
+}
+
+/// Whether `entity` has any of the `markers`.
+pub(crate) fn has_any(world: &World, entity: Entity, markers: &[ComponentId]) -> bool {
+    !markers.is_empty() && {
+        let entity = world.entity(entity);
+        markers.iter().any(|marker| entity.contains_id(*marker))
+    }
+}
@@ -1,61 +1,260 @@
-use bevy::{ecs::component::Component, prelude::*, reflect::FromType};
+use std::any::TypeId;
+
+use bevy::{
+    ecs::system::Resource,
+    prelude::*,
+    reflect::{FromReflect, FromType, GetTypeRegistration, TypeRegistryInternal},
+};
+
+use crate::SnapError;
 
 // Special thanks to https://github.com/jamescarterbell for this piece of code
 
+type AddResource = fn(&mut World, &dyn Reflect) -> Result<(), SnapError>;
+type CopyResource = fn(&World, &mut World) -> Result<(), SnapError>;
+
 #[derive(Clone)]
 pub struct ReflectResource {
-    add_resource: fn(&mut World, &dyn Reflect),
+    check_resource: fn(&dyn Reflect) -> Result<(), SnapError>,
+    add_resource: Option<AddResource>,
+    insert_resource: fn(&mut World, Box<dyn Reflect>) -> Result<(), SnapError>,
     remove_resource: fn(&mut World),
-    apply_resource: fn(&mut World, &dyn Reflect),
+    apply_resource: fn(&mut World, &dyn Reflect) -> Result<(), SnapError>,
     reflect_resource: fn(&World) -> Option<&dyn Reflect>,
-    copy_resource: fn(&World, &mut World),
+    copy_resource: Option<CopyResource>,
+    type_name: &'static str,
 }
 
 impl ReflectResource {
-    pub(crate) fn add_resource(&self, world: &mut World, resource: &dyn Reflect) {
-        (self.add_resource)(world, resource);
+    /// Makes sure `resource` can be added or applied, without touching any world.
+    pub(crate) fn check_resource(&self, resource: &dyn Reflect) -> Result<(), SnapError> {
+        (self.check_resource)(resource)
+    }
+
+    /// Whether the type can create new instances by itself, without `ReflectDefault`.
+    pub(crate) fn has_constructor(&self) -> bool {
+        self.add_resource.is_some()
+    }
+
+    /// Adds `resource` to `world`. Types without a constructor of their own start out from
+    /// `default`, the `ReflectDefault` of their registration.
+    pub(crate) fn add_resource(
+        &self,
+        world: &mut World,
+        resource: &dyn Reflect,
+        default: Option<&ReflectDefault>,
+    ) -> Result<(), SnapError> {
+        if let Some(add_resource) = self.add_resource {
+            return add_resource(world, resource);
+        }
+        (self.check_resource)(resource)?;
+        let mut value = default
+            .ok_or_else(|| SnapError::MissingConstructor(self.type_name.to_string()))?
+            .default();
+        value.apply(resource);
+        (self.insert_resource)(world, value)
     }
 
     pub(crate) fn remove_resource(&self, world: &mut World) {
         (self.remove_resource)(world);
     }
 
-    pub(crate) fn apply_resource(&self, world: &mut World, resource: &dyn Reflect) {
-        (self.apply_resource)(world, resource);
+    pub(crate) fn apply_resource(
+        &self,
+        world: &mut World,
+        resource: &dyn Reflect,
+    ) -> Result<(), SnapError> {
+        (self.apply_resource)(world, resource)
     }
 
     pub(crate) fn reflect_resource<'a>(&self, world: &'a World) -> Option<&'a dyn Reflect> {
         (self.reflect_resource)(world)
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn copy_resource(&self, source_world: &World, destination_world: &mut World) {
-        (self.copy_resource)(source_world, destination_world);
+    /// Copies the resource of `source_world` to `destination_world`, see `add_resource`.
+    pub(crate) fn copy_resource(
+        &self,
+        source_world: &World,
+        destination_world: &mut World,
+        default: Option<&ReflectDefault>,
+    ) -> Result<(), SnapError> {
+        if let Some(copy_resource) = self.copy_resource {
+            return copy_resource(source_world, destination_world);
+        }
+        let source_resource = (self.reflect_resource)(source_world)
+            .ok_or_else(|| SnapError::MissingResource(self.type_name.to_string()))?;
+        self.add_resource(destination_world, source_resource, default)
+    }
+}
+
+/// Creates default values of a type through reflection, added with `#[reflect(Default)]`.
+///
+/// Resources whose `ReflectResource` can't create them by itself, see
+/// `ReflectResource::without_constructor`, start out from this value when they are loaded.
+#[derive(Clone)]
+pub struct ReflectDefault {
+    default: fn() -> Box<dyn Reflect>,
+}
+
+impl ReflectDefault {
+    /// A default value of the type.
+    pub fn default(&self) -> Box<dyn Reflect> {
+        (self.default)()
+    }
+}
+
+impl<C: Reflect + Default> FromType<C> for ReflectDefault {
+    fn from_type() -> Self {
+        ReflectDefault {
+            default: || Box::new(C::default()),
+        }
+    }
+}
+
+/// Makes sure `value` can be applied to a `C`.
+fn check_type<C: Reflect>(value: &dyn Reflect) -> Result<(), SnapError> {
+    let expected = std::any::type_name::<C>();
+    if value.type_name() == expected {
+        Ok(())
+    } else {
+        Err(SnapError::TypeMismatch {
+            expected: expected.to_string(),
+            found: value.type_name().to_string(),
+        })
     }
 }
 
-impl<C: Component + Reflect + FromWorld> FromType<C> for ReflectResource {
+fn missing_resource<C>() -> SnapError {
+    SnapError::MissingResource(std::any::type_name::<C>().to_string())
+}
+
+/// Creates a `C` from `value`, for resources without `FromWorld`.
+fn from_reflect<C: FromReflect>(value: &dyn Reflect) -> Result<C, SnapError> {
+    check_type::<C>(value)?;
+    C::from_reflect(value).ok_or_else(|| SnapError::TypeMismatch {
+        expected: std::any::type_name::<C>().to_string(),
+        found: value.type_name().to_string(),
+    })
+}
+
+fn apply_resource<C: Resource + Reflect>(
+    world: &mut World,
+    reflected_resource: &dyn Reflect,
+) -> Result<(), SnapError> {
+    check_type::<C>(reflected_resource)?;
+    let mut resource = world
+        .get_resource_mut::<C>()
+        .ok_or_else(missing_resource::<C>)?;
+    resource.apply(reflected_resource);
+    Ok(())
+}
+
+fn insert_resource<C: Resource + Reflect>(
+    world: &mut World,
+    value: Box<dyn Reflect>,
+) -> Result<(), SnapError> {
+    let value = value
+        .downcast::<C>()
+        .map_err(|value| SnapError::TypeMismatch {
+            expected: std::any::type_name::<C>().to_string(),
+            found: value.type_name().to_string(),
+        })?;
+    world.insert_resource(*value);
+    Ok(())
+}
+
+fn remove_resource<C: Resource>(world: &mut World) {
+    world.remove_resource::<C>();
+}
+
+fn reflect_resource<C: Resource + Reflect>(world: &World) -> Option<&dyn Reflect> {
+    world.get_resource::<C>().map(|c| c as &dyn Reflect)
+}
+
+impl<C: Resource + Reflect + FromWorld> FromType<C> for ReflectResource {
     fn from_type() -> Self {
         ReflectResource {
-            add_resource: |world, reflected_resource| {
+            check_resource: check_type::<C>,
+            add_resource: Some(|world, reflected_resource| {
+                check_type::<C>(reflected_resource)?;
                 let mut resource = C::from_world(world);
                 resource.apply(reflected_resource);
                 world.insert_resource(resource);
-            },
-            remove_resource: |world| {
-                world.remove_resource::<C>();
-            },
-            apply_resource: |world, reflected_resource| {
-                let mut resource = world.get_resource_mut::<C>().unwrap();
-                resource.apply(reflected_resource);
-            },
-            copy_resource: |source_world, destination_world| {
-                let source_resource = source_world.get_resource::<C>().unwrap();
+                Ok(())
+            }),
+            insert_resource: insert_resource::<C>,
+            remove_resource: remove_resource::<C>,
+            apply_resource: apply_resource::<C>,
+            copy_resource: Some(|source_world, destination_world| {
+                let source_resource = source_world
+                    .get_resource::<C>()
+                    .ok_or_else(missing_resource::<C>)?;
                 let mut destination_resource = C::from_world(destination_world);
                 destination_resource.apply(source_resource);
                 destination_world.insert_resource(destination_resource);
-            },
-            reflect_resource: |world| world.get_resource::<C>().map(|c| c as &dyn Reflect),
+                Ok(())
+            }),
+            reflect_resource: reflect_resource::<C>,
+            type_name: std::any::type_name::<C>(),
         }
     }
 }
+
+impl ReflectResource {
+    /// Type data for resources that don't implement `FromWorld` or `Default`. New instances are
+    /// created from the saved value alone, with `FromReflect`.
+    ///
+    /// See `register_resource_from_reflect`.
+    pub fn from_reflect<C: Resource + FromReflect>() -> Self {
+        ReflectResource {
+            check_resource: |reflected_resource| from_reflect::<C>(reflected_resource).map(|_| ()),
+            add_resource: Some(|world, reflected_resource| {
+                world.insert_resource(from_reflect::<C>(reflected_resource)?);
+                Ok(())
+            }),
+            insert_resource: insert_resource::<C>,
+            remove_resource: remove_resource::<C>,
+            apply_resource: apply_resource::<C>,
+            copy_resource: Some(|source_world, destination_world| {
+                let source_resource = source_world
+                    .get_resource::<C>()
+                    .ok_or_else(missing_resource::<C>)?;
+                destination_world.insert_resource(from_reflect::<C>(source_resource)?);
+                Ok(())
+            }),
+            reflect_resource: reflect_resource::<C>,
+            type_name: std::any::type_name::<C>(),
+        }
+    }
+
+    /// Type data for resources that can't create new instances by themselves. Saved values are
+    /// applied to the resource in the world, and a missing resource is created from the
+    /// `ReflectDefault` of the type's registration.
+    ///
+    /// Registering such a type without `ReflectDefault` fails with `SnapError::MissingConstructor`.
+    pub fn without_constructor<C: Resource + Reflect>() -> Self {
+        ReflectResource {
+            check_resource: check_type::<C>,
+            add_resource: None,
+            insert_resource: insert_resource::<C>,
+            remove_resource: remove_resource::<C>,
+            apply_resource: apply_resource::<C>,
+            copy_resource: None,
+            reflect_resource: reflect_resource::<C>,
+            type_name: std::any::type_name::<C>(),
+        }
+    }
+}
+
+/// Registers `C` as a resource that is created with `FromReflect` when it is loaded, see
+/// `ReflectResource::from_reflect`.
+///
+/// Resources that implement `Default` or `FromWorld` only need `#[reflect(Resource)]`.
+pub fn register_resource_from_reflect<C>(registry: &mut TypeRegistryInternal)
+where
+    C: Resource + FromReflect + GetTypeRegistration,
+{
+    registry.register::<C>();
+    let registration = registry.get_mut(TypeId::of::<C>()).unwrap();
+    registration.insert(ReflectResource::from_reflect::<C>());
+}
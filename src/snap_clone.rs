@@ -0,0 +1,154 @@
+use std::any::{Any, TypeId};
+
+use bevy::{
+    ecs::{archetype::Archetype, component::Component},
+    prelude::*,
+    reflect::{FromType, GetTypeRegistration, TypeRegistryInternal},
+};
+
+use crate::world_snapshot::column_values;
+
+/// Values of one component type, for the tagged entities of one archetype.
+pub(crate) struct ComponentColumn {
+    pub type_id: TypeId,
+    pub type_name: &'static str,
+    /// Indices into `WorldSnapshot::entities`, in the same order as the values.
+    pub entities: Vec<usize>,
+    pub values: Box<dyn ColumnValues>,
+}
+
+impl ComponentColumn {
+    /// The value for the `i`th entry in `entities`.
+    pub fn get(&self, i: usize) -> &dyn Reflect {
+        self.values.get(i)
+    }
+}
+
+impl Clone for ComponentColumn {
+    fn clone(&self) -> Self {
+        Self {
+            type_id: self.type_id,
+            type_name: self.type_name,
+            entities: self.entities.clone(),
+            values: self.values.clone_values(),
+        }
+    }
+}
+
+impl std::fmt::Debug for ComponentColumn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ComponentColumn")
+            .field("type_name", &self.type_name)
+            .field("len", &self.entities.len())
+            .finish()
+    }
+}
+
+pub(crate) trait ColumnValues: Send + Sync {
+    fn get(&self, i: usize) -> &dyn Reflect;
+    fn clone_values(&self) -> Box<dyn ColumnValues>;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<C: Component + Reflect + Clone> ColumnValues for Vec<C> {
+    fn get(&self, i: usize) -> &dyn Reflect {
+        &self[i]
+    }
+
+    fn clone_values(&self) -> Box<dyn ColumnValues> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The cloned values of a column, and the sum of their hashes.
+pub(crate) type CapturedValues = (Box<dyn ColumnValues>, u64);
+
+/// Opts a component into the typed fast path: it is stored by value in the snapshot and restored
+/// with `Clone`, instead of going through reflection.
+///
+/// Add it with `#[reflect(Component, SnapClone)]`, or with `register_snap_clone` for types you
+/// don't own. The values are still available as `dyn Reflect`, see
+/// `WorldSnapshot::make_reflective`.
+#[derive(Clone)]
+pub struct ReflectSnapClone {
+    capture: fn(&World, &Archetype, &[Entity], &[usize]) -> CapturedValues,
+    write: fn(&mut World, &ComponentColumn, &[Option<Entity>], &mut Vec<Entity>),
+}
+
+impl ReflectSnapClone {
+    /// Clones the component of each entity, which all belong to `archetype` and are in
+    /// `table_rows` of its table, and sums their hashes.
+    pub(crate) fn capture(
+        &self,
+        world: &World,
+        archetype: &Archetype,
+        entities: &[Entity],
+        table_rows: &[usize],
+    ) -> CapturedValues {
+        (self.capture)(world, archetype, entities, table_rows)
+    }
+
+    /// Writes `column` to the `targets` of its entities, and pushes the entities that didn't have
+    /// the component to `added`.
+    pub(crate) fn write(
+        &self,
+        world: &mut World,
+        column: &ComponentColumn,
+        targets: &[Option<Entity>],
+        added: &mut Vec<Entity>,
+    ) {
+        (self.write)(world, column, targets, added)
+    }
+}
+
+impl<C: Component + Reflect + Clone> FromType<C> for ReflectSnapClone {
+    fn from_type() -> Self {
+        ReflectSnapClone {
+            capture: |world, archetype, entities, table_rows| {
+                let mut checksum = 0u64;
+                let values = column_values::<C>(world, archetype, entities, table_rows)
+                    .into_iter()
+                    .map(|value| {
+                        let value = value.unwrap();
+                        if let Some(hash) = value.reflect_hash() {
+                            checksum = checksum.wrapping_add(hash);
+                        }
+                        value.clone()
+                    })
+                    .collect::<Vec<_>>();
+                (Box::new(values), checksum)
+            },
+            write: |world, column, targets, added| {
+                let values = column.values.as_any().downcast_ref::<Vec<C>>().unwrap();
+                for (index, value) in column.entities.iter().zip(values.iter()) {
+                    let entity = match targets[*index] {
+                        Some(entity) => entity,
+                        None => continue,
+                    };
+                    match world.get_mut::<C>(entity) {
+                        Some(mut component) => *component = value.clone(),
+                        None => {
+                            world.entity_mut(entity).insert(value.clone());
+                            added.push(entity);
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Registers `C` as a component that uses the typed fast path, see `ReflectSnapClone`.
+pub fn register_snap_clone<C>(registry: &mut TypeRegistryInternal)
+where
+    C: Component + Reflect + Clone + FromWorld + GetTypeRegistration,
+{
+    registry.register::<C>();
+    let registration = registry.get_mut(TypeId::of::<C>()).unwrap();
+    registration.insert(<ReflectComponent as FromType<C>>::from_type());
+    registration.insert(<ReflectSnapClone as FromType<C>>::from_type());
+}
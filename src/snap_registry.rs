@@ -0,0 +1,128 @@
+use std::{any::TypeId, marker::PhantomData};
+
+use bevy::{
+    prelude::*,
+    reflect::{GetTypeRegistration, TypeRegistration, TypeRegistry, TypeRegistryInternal},
+};
+
+use crate::{world_snapshot::check_registration, SnapError, SnapFlow, SnapHooks, SnapType};
+
+/// The types saved and loaded by `T`, starting out with the ones from `SnapType::add_types`.
+///
+/// Added as a resource by the `SnapPlugin`, so plugins and mods can register more types at runtime,
+/// or pick them by name from the app's `TypeRegistry`.
+pub struct SnapRegistry<T: SnapType> {
+    pub(crate) type_registry: TypeRegistry,
+    pub(crate) hooks: SnapHooks,
+    pub(crate) flow: SnapFlow,
+    t: PhantomData<T>,
+}
+
+impl<T: SnapType> Default for SnapRegistry<T> {
+    fn default() -> Self {
+        let mut type_registry = TypeRegistry::default();
+        T::add_types(&mut type_registry);
+        let mut hooks = SnapHooks::default();
+        T::add_hooks(&mut hooks);
+        let mut flow = SnapFlow::default();
+        T::add_flow(&mut flow);
+        Self {
+            type_registry,
+            hooks,
+            flow,
+            t: default(),
+        }
+    }
+}
+
+impl<T: SnapType> SnapRegistry<T> {
+    /// The registered types.
+    pub fn type_registry(&self) -> &TypeRegistry {
+        &self.type_registry
+    }
+
+    /// Registers `C`, which has to be `#[reflect(Component)]` or `#[reflect(Resource)]`.
+    pub fn register<C: GetTypeRegistration>(&mut self) -> Result<(), SnapError> {
+        self.add_registration(C::get_type_registration())
+    }
+
+    /// Registers the type described by `registration`.
+    pub fn add_registration(&mut self, registration: TypeRegistration) -> Result<(), SnapError> {
+        check_registration(&registration)?;
+        self.type_registry.write().add_registration(registration);
+        Ok(())
+    }
+
+    /// Registers the type called `name` in `app_registry`, usually the app's `TypeRegistry`
+    /// resource. Both full and short type names work.
+    pub fn register_by_name(
+        &mut self,
+        app_registry: &TypeRegistryInternal,
+        name: &str,
+    ) -> Result<(), SnapError> {
+        self.add_registration(find_by_name(app_registry, name)?.clone())
+    }
+
+    /// Registers each of `names`, e.g. read from a config file, see `register_by_name`.
+    ///
+    /// Nothing is registered if any of the names is unknown or can't be saved.
+    pub fn register_names(
+        &mut self,
+        app_registry: &TypeRegistryInternal,
+        names: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<(), SnapError> {
+        let mut registrations = Vec::new();
+        for name in names {
+            let name = name.as_ref().trim();
+            if name.is_empty() {
+                continue;
+            }
+            registrations.push(find_by_name(app_registry, name)?.clone());
+        }
+        registrations.iter().try_for_each(check_registration)?;
+        registrations
+            .into_iter()
+            .try_for_each(|registration| self.add_registration(registration))
+    }
+
+    /// Stops saving and loading `C`. Returns whether it was registered.
+    pub fn unregister<C: 'static>(&mut self) -> bool {
+        self.unregister_type_id(TypeId::of::<C>())
+    }
+
+    /// Stops saving and loading the type called `name`. Returns whether it was registered.
+    pub fn unregister_by_name(&mut self, name: &str) -> bool {
+        let type_id = find_by_name(&self.type_registry.read(), name)
+            .map(|registration| registration.type_id())
+            .ok();
+        type_id.map_or(false, |type_id| self.unregister_type_id(type_id))
+    }
+
+    fn unregister_type_id(&mut self, type_id: TypeId) -> bool {
+        let mut type_registry = self.type_registry.write();
+        if type_registry.get(type_id).is_none() {
+            return false;
+        }
+        // the registry can't remove types, so rebuild it without this one
+        let mut rebuilt = TypeRegistryInternal::default();
+        for registration in type_registry
+            .iter()
+            .filter(|registration| registration.type_id() != type_id)
+        {
+            rebuilt.add_registration(registration.clone());
+        }
+        *type_registry = rebuilt;
+        true
+    }
+}
+
+/// Looks up a type by its full or short name.
+fn find_by_name<'a>(
+    type_registry: &'a TypeRegistryInternal,
+    name: &str,
+) -> Result<&'a TypeRegistration, SnapError> {
+    type_registry
+        .get_with_name(name)
+        .or_else(|| type_registry.get_with_short_name(name))
+        .ok_or_else(|| SnapError::UnknownType(name.to_string()))
+}
@@ -0,0 +1,139 @@
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+use uuid::Uuid;
+
+use crate::SnapType;
+
+/// Identity of a tracked entity, used to match snapshot entities with entities in the world.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SnapshotKey {
+    /// Sequential id, usually handed out by `SnapshotIdProvider`.
+    Index(u32),
+    /// Stable name, e.g. for entities placed by level designers.
+    Name(String),
+    /// 128-bit UUID, e.g. derived from a spawn path with `SnapshotKey::from_path`.
+    Uuid(Uuid),
+}
+
+impl SnapshotKey {
+    /// Returns the sequential id, if this is an `Index` key.
+    pub fn index(&self) -> Option<u32> {
+        match self {
+            SnapshotKey::Index(index) => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// Derives a deterministic version 5 UUID from a path of names, e.g.
+    /// `["forest", "camp", "chest"]`.
+    ///
+    /// The same path always gives the same key, across runs, platforms and crate versions.
+    pub fn from_path<I, S>(path: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut name = Vec::new();
+        for segment in path {
+            name.extend_from_slice(segment.as_ref().as_bytes());
+            // separate segments, so ["ab", "c"] and ["a", "bc"] differ
+            name.push(0xff);
+        }
+        SnapshotKey::Uuid(Uuid::new_v5(&SNAPSHOT_KEY_NAMESPACE, &name))
+    }
+
+    /// Derives a deterministic key for a child spawned under this key with the given name.
+    pub fn child(&self, name: &str) -> Self {
+        let mut bytes = Vec::new();
+        match self {
+            SnapshotKey::Index(index) => {
+                bytes.push(0);
+                bytes.extend_from_slice(&index.to_le_bytes());
+            }
+            SnapshotKey::Name(parent_name) => {
+                bytes.push(1);
+                bytes.extend_from_slice(parent_name.as_bytes());
+            }
+            SnapshotKey::Uuid(uuid) => {
+                bytes.push(2);
+                bytes.extend_from_slice(uuid.as_bytes());
+            }
+        }
+        bytes.push(0xff);
+        bytes.extend_from_slice(name.as_bytes());
+        SnapshotKey::Uuid(Uuid::new_v5(&SNAPSHOT_KEY_NAMESPACE, &bytes))
+    }
+}
+
+impl From<u32> for SnapshotKey {
+    fn from(index: u32) -> Self {
+        SnapshotKey::Index(index)
+    }
+}
+
+impl From<&str> for SnapshotKey {
+    fn from(name: &str) -> Self {
+        SnapshotKey::Name(name.to_string())
+    }
+}
+
+impl From<String> for SnapshotKey {
+    fn from(name: String) -> Self {
+        SnapshotKey::Name(name)
+    }
+}
+
+impl From<Uuid> for SnapshotKey {
+    fn from(uuid: Uuid) -> Self {
+        SnapshotKey::Uuid(uuid)
+    }
+}
+
+/// Namespace of the UUIDs derived by `SnapshotKey::from_path` and `SnapshotKey::child`.
+const SNAPSHOT_KEY_NAMESPACE: Uuid = Uuid::from_u128(0x5d6c_2a1e_7f3b_4c8d_9e0a_1b2c_3d4e_5f60);
+
+/// Add this component to all entities you want to be loaded/saved in snapshots.
+/// The `id` has to be unique. Consider using the `SnapshotIdProvider` resource for sequential ids,
+/// or `SnapshotId::named`/`SnapshotId::from_path` for ids that need to be stable across level versions.
+#[derive(Component)]
+pub struct SnapshotId<T: SnapType> {
+    id: SnapshotKey,
+    t: PhantomData<T>,
+}
+
+impl<T: SnapType> SnapshotId<T> {
+    pub fn new(id: u32) -> Self {
+        Self::from_key(SnapshotKey::Index(id))
+    }
+
+    pub fn named(name: impl Into<String>) -> Self {
+        Self::from_key(SnapshotKey::Name(name.into()))
+    }
+
+    pub fn uuid(uuid: Uuid) -> Self {
+        Self::from_key(SnapshotKey::Uuid(uuid))
+    }
+
+    /// See `SnapshotKey::from_path`.
+    pub fn from_path<I, S>(path: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self::from_key(SnapshotKey::from_path(path))
+    }
+
+    pub fn from_key(id: SnapshotKey) -> Self {
+        Self { id, t: default() }
+    }
+
+    /// See `SnapshotKey::child`.
+    pub fn child(&self, name: &str) -> Self {
+        Self::from_key(self.id.child(name))
+    }
+
+    pub fn id(&self) -> &SnapshotKey {
+        &self.id
+    }
+}
@@ -1,23 +1,76 @@
-use std::marker::PhantomData;
+use std::{collections::BTreeSet, fmt::Debug, marker::PhantomData};
 
 use crate::{SnapType, SnapshotId};
 
 /// Provides SnapshotId components
+///
+/// The provider state is saved in every `WorldSnapshot` and restored when the snapshot is
+/// loaded, so ids handed out after loading never collide with restored entities.
 #[derive(Default)]
 pub struct SnapshotIdProvider<T: SnapType> {
     next_id: u32,
+    free_ids: BTreeSet<u32>,
     t: PhantomData<T>,
 }
 
+impl<T: SnapType> Clone for SnapshotIdProvider<T> {
+    fn clone(&self) -> Self {
+        Self {
+            next_id: self.next_id,
+            free_ids: self.free_ids.clone(),
+            t: PhantomData,
+        }
+    }
+}
+
+impl<T: SnapType> Debug for SnapshotIdProvider<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SnapshotIdProvider")
+            .field("next_id", &self.next_id)
+            .field("free_ids", &self.free_ids)
+            .finish()
+    }
+}
+
 impl<T: SnapType> SnapshotIdProvider<T> {
     /// Returns an unused, unique id.
+    ///
+    /// Released ids are handed out again, lowest first. Panics if all ids are in use, use
+    /// [`SnapshotIdProvider::try_next`] to handle that case yourself.
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> SnapshotId<T> {
+        self.try_next().expect(
+            "SnapshotIdProvider: u32::MAX has been reached. Release unused ids, or use try_next.",
+        )
+    }
+
+    /// Returns an unused, unique id, or `None` if all ids are in use.
+    pub fn try_next(&mut self) -> Option<SnapshotId<T>> {
+        if let Some(id) = self.free_ids.iter().next().copied() {
+            self.free_ids.remove(&id);
+            return Some(SnapshotId::new(id));
+        }
         if self.next_id == u32::MAX {
-            // TODO: do something smart?
-            panic!("SnapshotIdProvider: u32::MAX has been reached.");
+            return None;
         }
         let id = self.next_id;
         self.next_id += 1;
-        SnapshotId::new(id)
+        Some(SnapshotId::new(id))
+    }
+
+    /// Makes `id` available to be handed out again, e.g. after despawning its entity.
+    pub fn release(&mut self, id: u32) {
+        if id < self.next_id {
+            self.free_ids.insert(id);
+        }
+    }
+
+    /// Makes sure `id` is never handed out, e.g. when tagging an entity with a hand-picked id.
+    pub fn reserve(&mut self, id: u32) {
+        if id < self.next_id {
+            self.free_ids.remove(&id);
+        } else {
+            self.next_id = id.saturating_add(1);
+        }
     }
 }
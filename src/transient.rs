@@ -0,0 +1,110 @@
+use bevy::{
+    prelude::*,
+    reflect::{DynamicStruct, FromType, ReflectRef, Struct},
+};
+
+use crate::SnapError;
+
+/// Lists the fields of a struct that should never end up in a snapshot, like VFX timers or cached
+/// handles. Add `#[reflect(SnapTransient)]` to the type to make bevy_snap pick it up.
+pub trait SnapTransientFields {
+    /// Names of the fields to leave out of snapshots.
+    const TRANSIENT_FIELDS: &'static [&'static str];
+    /// Reset the fields to their default values when a snapshot is loaded, instead of leaving them
+    /// untouched.
+    const RESET_ON_LOAD: bool = false;
+}
+
+/// Type data for `SnapTransientFields`.
+#[derive(Clone)]
+pub struct ReflectSnapTransient {
+    fields: &'static [&'static str],
+    defaults: Option<fn() -> DynamicStruct>,
+    hash: fn(&dyn Reflect) -> Option<u64>,
+    unknown_field: Option<&'static str>,
+}
+
+impl ReflectSnapTransient {
+    /// Clones `value`, without the transient fields.
+    pub(crate) fn strip(&self, value: &dyn Reflect) -> Box<dyn Reflect> {
+        match value.reflect_ref() {
+            ReflectRef::Struct(value) => {
+                Box::new(filter_fields(value, |name| !self.fields.contains(&name)))
+            }
+            // only named fields can be left out
+            _ => value.clone_value(),
+        }
+    }
+
+    /// The default values of the transient fields, if they should be reset on load.
+    pub(crate) fn defaults(&self) -> Option<DynamicStruct> {
+        self.defaults.map(|defaults| defaults())
+    }
+
+    /// The hash of `value`, ignoring the transient fields.
+    pub(crate) fn hash(&self, value: &dyn Reflect) -> Option<u64> {
+        (self.hash)(value)
+    }
+
+    /// Makes sure all transient fields exist.
+    pub(crate) fn check(&self, type_name: &str) -> Result<(), SnapError> {
+        match self.unknown_field {
+            Some(field) => Err(SnapError::UnknownTransientField {
+                type_name: type_name.to_string(),
+                field: field.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<C: SnapTransientFields + Struct + Default> FromType<C> for ReflectSnapTransient {
+    fn from_type() -> Self {
+        ReflectSnapTransient {
+            fields: C::TRANSIENT_FIELDS,
+            defaults: C::RESET_ON_LOAD.then(|| transient_defaults::<C> as fn() -> DynamicStruct),
+            hash: transient_hash::<C>,
+            unknown_field: {
+                let value = C::default();
+                C::TRANSIENT_FIELDS
+                    .iter()
+                    .copied()
+                    .find(|name| value.field(name).is_none())
+            },
+        }
+    }
+}
+
+/// Hashes `value` as a `C` whose transient fields have their default values, so they can't change
+/// the hash.
+fn transient_hash<C: SnapTransientFields + Struct + Default>(value: &dyn Reflect) -> Option<u64> {
+    let value = match value.reflect_ref() {
+        ReflectRef::Struct(value) => value,
+        _ => return value.reflect_hash(),
+    };
+    let mut rebuilt = C::default();
+    for (i, field) in value.iter_fields().enumerate() {
+        let name = value.name_at(i).unwrap();
+        if !C::TRANSIENT_FIELDS.contains(&name) {
+            rebuilt.field_mut(name)?.apply(field);
+        }
+    }
+    rebuilt.reflect_hash()
+}
+
+fn transient_defaults<C: SnapTransientFields + Struct + Default>() -> DynamicStruct {
+    filter_fields(&C::default(), |name| C::TRANSIENT_FIELDS.contains(&name))
+}
+
+/// Clones the fields of `value` that satisfy `keep`.
+fn filter_fields(value: &dyn Struct, keep: impl Fn(&str) -> bool) -> DynamicStruct {
+    let mut filtered = DynamicStruct::default();
+    filtered.set_name(value.type_name().to_string());
+    for (i, field) in value.iter_fields().enumerate() {
+        let name = value.name_at(i).unwrap();
+        if keep(name) {
+            filtered.insert_boxed(name, field.clone_value());
+        }
+    }
+    filtered
+}
@@ -0,0 +1,263 @@
+use std::{fmt::Display, marker::PhantomData};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{SnapSettings, SnapType, SnapshotId, SnapshotIdProvider, SnapshotKey, WorldSnapshot};
+
+/// What to do when an entity is tagged with a `SnapshotId` that is already in use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicateIdPolicy {
+    /// Log an error and send a `DuplicateSnapshotIdEvent`.
+    SendEvent,
+    /// Give the newly tagged entities fresh ids from the `SnapshotIdProvider`. If it has run out of
+    /// ids, a `DuplicateSnapshotIdEvent` is sent instead.
+    Reassign,
+    /// Panic, useful to catch mistakes early during development.
+    Panic,
+}
+
+impl Default for DuplicateIdPolicy {
+    fn default() -> Self {
+        DuplicateIdPolicy::SendEvent
+    }
+}
+
+/// Sent when several entities are tagged with the same `SnapshotId`, and the
+/// `DuplicateIdPolicy` is `SendEvent`.
+pub struct DuplicateSnapshotIdEvent<T: SnapType> {
+    pub id: SnapshotKey,
+    /// The entities sharing the id. The entity that had the id first comes first.
+    pub entities: Vec<Entity>,
+    t: PhantomData<T>,
+}
+
+/// Problems that would make a snapshot load incorrectly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SnapshotIssue {
+    /// Several snapshot entities share the same id.
+    DuplicateId(SnapshotKey),
+    /// An entity refers to a parent that is not in the snapshot.
+    MissingParent {
+        id: SnapshotKey,
+        parent: SnapshotKey,
+    },
+    /// An entity refers to a child that is not in the snapshot.
+    MissingChild { id: SnapshotKey, child: SnapshotKey },
+    /// An entity lists a child that doesn't list it as its parent.
+    InconsistentHierarchy { id: SnapshotKey, child: SnapshotKey },
+    /// The same resource type is stored more than once.
+    DuplicateResource(String),
+}
+
+impl Display for SnapshotIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotIssue::DuplicateId(id) => write!(f, "duplicate snapshot id {:?}", id),
+            SnapshotIssue::MissingParent { id, parent } => {
+                write!(
+                    f,
+                    "{:?} has parent {:?}, which is not in the snapshot",
+                    id, parent
+                )
+            }
+            SnapshotIssue::MissingChild { id, child } => {
+                write!(
+                    f,
+                    "{:?} has child {:?}, which is not in the snapshot",
+                    id, child
+                )
+            }
+            SnapshotIssue::InconsistentHierarchy { id, child } => {
+                write!(
+                    f,
+                    "{:?} has child {:?}, but it has a different parent",
+                    id, child
+                )
+            }
+            SnapshotIssue::DuplicateResource(name) => write!(f, "duplicate resource {}", name),
+        }
+    }
+}
+
+impl<T: SnapType> WorldSnapshot<T> {
+    /// Checks the snapshot for duplicate ids and inconsistencies, without touching any world.
+    pub fn validate(&self) -> Result<(), Vec<SnapshotIssue>> {
+        let mut issues = Vec::new();
+
+        let mut parents = HashMap::default();
+        for snapshot_entity in self.entities.iter() {
+            if parents
+                .insert(&snapshot_entity.snapshot_id, &snapshot_entity.parent)
+                .is_some()
+            {
+                issues.push(SnapshotIssue::DuplicateId(
+                    snapshot_entity.snapshot_id.clone(),
+                ));
+            }
+        }
+
+        for snapshot_entity in self.entities.iter() {
+            let id = &snapshot_entity.snapshot_id;
+            if let Some(parent) = &snapshot_entity.parent {
+                if !parents.contains_key(parent) {
+                    issues.push(SnapshotIssue::MissingParent {
+                        id: id.clone(),
+                        parent: parent.clone(),
+                    });
+                }
+            }
+            for child in snapshot_entity.children.iter() {
+                match parents.get(child) {
+                    None => issues.push(SnapshotIssue::MissingChild {
+                        id: id.clone(),
+                        child: child.clone(),
+                    }),
+                    Some(child_parent) if child_parent.as_ref() != Some(id) => {
+                        issues.push(SnapshotIssue::InconsistentHierarchy {
+                            id: id.clone(),
+                            child: child.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        let mut resource_types = HashSet::default();
+        for resource in self.resources.iter() {
+            if !resource_types.insert(resource.type_name()) {
+                issues.push(SnapshotIssue::DuplicateResource(
+                    resource.type_name().to_string(),
+                ));
+            }
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+}
+
+/// The entity each id belongs to, kept up to date by `detect_duplicate_ids` so it only has to look
+/// at newly tagged entities.
+#[derive(Default)]
+pub(crate) struct IdOwners {
+    by_key: HashMap<SnapshotKey, Entity>,
+    by_entity: HashMap<Entity, SnapshotKey>,
+}
+
+impl IdOwners {
+    fn insert(&mut self, key: SnapshotKey, entity: Entity) {
+        self.remove(entity);
+        self.by_entity.insert(entity, key.clone());
+        self.by_key.insert(key, entity);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(key) = self.by_entity.remove(&entity) {
+            if self.by_key.get(&key) == Some(&entity) {
+                self.by_key.remove(&key);
+            }
+        }
+    }
+}
+
+/// Detects entities that were tagged with an id that is already in use, and handles them
+/// according to the `DuplicateIdPolicy`.
+///
+/// Only entities whose `SnapshotId` was added or replaced since the last run are checked.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn detect_duplicate_ids<T: SnapType>(
+    mut commands: Commands,
+    mut owners: Local<IdOwners>,
+    changed: Query<(Entity, &SnapshotId<T>), Changed<SnapshotId<T>>>,
+    snapshot_ids: Query<&SnapshotId<T>>,
+    removed: RemovedComponents<SnapshotId<T>>,
+    settings: Res<SnapSettings<T>>,
+    mut id_provider: ResMut<SnapshotIdProvider<T>>,
+    mut duplicate_events: EventWriter<DuplicateSnapshotIdEvent<T>>,
+) {
+    for entity in removed.iter() {
+        owners.remove(entity);
+    }
+    if changed.is_empty() {
+        return;
+    }
+    // visit the entities in a deterministic order, so reassigned ids are the same for all peers
+    let mut changed = changed.iter().collect::<Vec<_>>();
+    changed.sort_by_key(|(entity, _)| entity.id());
+
+    let mut duplicates = HashMap::<SnapshotKey, Vec<Entity>>::default();
+    for (entity, snapshot_id) in changed {
+        let key = snapshot_id.id();
+        // the owner may have been despawned or retagged since it was recorded
+        let owner = owners.by_key.get(key).copied().filter(|owner| {
+            *owner != entity
+                && matches!(snapshot_ids.get(*owner), Ok(owner_id) if owner_id.id() == key)
+        });
+        match owner {
+            Some(owner) => {
+                owners.remove(entity);
+                // the entity that had the id first keeps it
+                duplicates
+                    .entry(key.clone())
+                    .or_insert_with(|| vec![owner])
+                    .push(entity);
+            }
+            None => owners.insert(key.clone(), entity),
+        }
+    }
+    let mut duplicates = duplicates.into_iter().collect::<Vec<_>>();
+    duplicates.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    for (id, entities) in duplicates {
+        match settings.duplicate_id_policy {
+            DuplicateIdPolicy::SendEvent => {
+                send_duplicate_event(&mut duplicate_events, id, entities);
+            }
+            DuplicateIdPolicy::Reassign => {
+                for (index, entity) in entities.iter().copied().enumerate().skip(1) {
+                    match id_provider.try_next() {
+                        Some(new_id) => {
+                            owners.insert(new_id.id().clone(), entity);
+                            commands.entity(entity).insert(new_id);
+                        }
+                        None => {
+                            error!("SnapshotIdProvider has no ids left to reassign");
+                            let remaining = [&entities[..1], &entities[index..]].concat();
+                            send_duplicate_event(&mut duplicate_events, id, remaining);
+                            break;
+                        }
+                    }
+                }
+            }
+            DuplicateIdPolicy::Panic => {
+                panic!(
+                    "Entities {:?} share the snapshot id {:?}. Did you insert the same id twice?",
+                    entities, id
+                );
+            }
+        }
+    }
+}
+
+fn send_duplicate_event<T: SnapType>(
+    duplicate_events: &mut EventWriter<DuplicateSnapshotIdEvent<T>>,
+    id: SnapshotKey,
+    entities: Vec<Entity>,
+) {
+    error!(
+        "Entities {:?} share the snapshot id {:?}, only one of them will be restored",
+        entities, id
+    );
+    duplicate_events.send(DuplicateSnapshotIdEvent {
+        id,
+        entities,
+        t: default(),
+    });
+}
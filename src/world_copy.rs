@@ -0,0 +1,222 @@
+use std::any::TypeId;
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    handles::ReflectSnapHandle,
+    hierarchy::{self, HierarchyEntry},
+    markers::{has_any, SnapMarkers},
+    reflect_resource::{ReflectDefault, ReflectResource},
+    transient::ReflectSnapTransient,
+    world_snapshot::{check_type_data, snapshot_id_map},
+    EntityMapping, LoadReport, SnapError, SnapRegistry, SnapType, SnapshotId, SnapshotIdProvider,
+};
+
+/// Makes the state tracked by `T` in `destination` match `source`, like saving a snapshot of
+/// `source` and loading it into `destination` with `LoadMode::Replace` would, but without
+/// creating the snapshot.
+///
+/// The types are taken from the `SnapRegistry<T>` of `source`, or of `destination` if `source`
+/// has none. Hooks are not run.
+pub(crate) fn copy_world<T: SnapType>(
+    source: &World,
+    destination: &mut World,
+) -> Result<LoadReport, SnapError> {
+    let registry = source
+        .get_resource::<SnapRegistry<T>>()
+        .or_else(|| destination.get_resource::<SnapRegistry<T>>())
+        .ok_or(SnapError::MissingRegistry(std::any::type_name::<T>()))?;
+    let type_registry = registry.type_registry.clone();
+    let flow = registry.flow.clone();
+    let type_registry = type_registry.read();
+    check_type_data(&type_registry)?;
+    let mut report = LoadReport::default();
+
+    // pair each tagged entity of the source with an entity in the destination
+    let mut destination_ids = snapshot_id_map::<T>(destination)?;
+    let tracked = destination_ids.values().copied().collect::<HashSet<_>>();
+    let source_entities = source
+        .components()
+        .get_id(TypeId::of::<SnapshotId<T>>())
+        .into_iter()
+        .flat_map(|snapshot_id| {
+            source
+                .archetypes()
+                .iter()
+                .filter(move |archetype| archetype.contains(snapshot_id))
+        })
+        .flat_map(|archetype| archetype.entities().iter().copied())
+        .collect::<Vec<_>>();
+    let mut entity_map = HashMap::default();
+    for source_entity in source_entities.iter().copied() {
+        let key = source.get::<SnapshotId<T>>(source_entity).unwrap().id();
+        if report.entities.contains_key(key) {
+            return Err(SnapError::DuplicateId(key.clone()));
+        }
+        let entity = match destination_ids.remove(key) {
+            Some(entity) => {
+                report.updated.push(key.clone());
+                entity
+            }
+            None => {
+                report.spawned.push(key.clone());
+                destination
+                    .spawn()
+                    .insert(SnapshotId::<T>::from_key(key.clone()))
+                    .id()
+            }
+        };
+        entity_map.insert(source_entity, entity);
+        report.entities.insert(
+            key.clone(),
+            EntityMapping {
+                old: source_entity,
+                new: entity,
+            },
+        );
+    }
+    let pairs = source_entities
+        .iter()
+        .map(|source_entity| (*source_entity, entity_map[source_entity]))
+        .collect::<Vec<_>>();
+
+    // copy the components, one type at a time
+    let source_markers = SnapMarkers::new(source, &type_registry);
+    let destination_markers = SnapMarkers::new(destination, &type_registry);
+    for registration in type_registry.iter() {
+        let type_id = registration.type_id();
+        if hierarchy::is_hierarchy_type(type_id) {
+            continue;
+        }
+        let reflect_component = match registration.data::<ReflectComponent>() {
+            Some(reflect_component) => reflect_component,
+            None => continue,
+        };
+        let excluded = source_markers.excluded(type_id);
+        let protected = destination_markers.protected(type_id);
+        let transient = registration.data::<ReflectSnapTransient>();
+        let transient_defaults = transient.and_then(|transient| transient.defaults());
+        let is_handle = registration.data::<ReflectSnapHandle>().is_some();
+
+        for (source_entity, entity) in pairs.iter().copied() {
+            if has_any(destination, entity, &protected) {
+                continue;
+            }
+            let component = reflect_component
+                .reflect_component(source, source_entity)
+                .filter(|_| {
+                    !excluded.map_or(false, |excluded| {
+                        has_any(source, source_entity, &[excluded])
+                    })
+                });
+            let existing = destination.entity(entity).contains_type_id(type_id);
+            match component {
+                // applying would only change the id of a handle, not which asset it keeps loaded
+                Some(_) if is_handle => {
+                    reflect_component.copy_component(source, destination, source_entity, entity);
+                }
+                Some(component) => {
+                    let stripped = transient.map(|transient| transient.strip(component));
+                    let component = stripped.as_deref().unwrap_or(component);
+                    if existing {
+                        reflect_component.apply_component(destination, entity, component);
+                        if let Some(defaults) = &transient_defaults {
+                            reflect_component.apply_component(destination, entity, defaults);
+                        }
+                    } else {
+                        reflect_component.add_component(destination, entity, component);
+                    }
+                }
+                None if existing => {
+                    reflect_component.remove_component(destination, entity);
+                    report
+                        .components_removed
+                        .push((entity, registration.name()));
+                    continue;
+                }
+                None => continue,
+            }
+            if !existing {
+                report.components_added.push((entity, registration.name()));
+            }
+        }
+    }
+
+    // restore the hierarchy between tagged entities, then despawn the ones the source doesn't have
+    let hierarchy_entries = pairs
+        .iter()
+        .map(|(source_entity, entity)| HierarchyEntry {
+            entity: *entity,
+            parent: source
+                .get::<Parent>(*source_entity)
+                .and_then(|parent| entity_map.get(&parent.0).copied()),
+            children: source
+                .get::<Children>(*source_entity)
+                .map(|children| {
+                    children
+                        .iter()
+                        .filter_map(|child| entity_map.get(child).copied())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
+    let tracked = tracked
+        .into_iter()
+        .chain(entity_map.values().copied())
+        .collect::<HashSet<_>>();
+    hierarchy::restore_hierarchy(destination, &hierarchy_entries, &tracked, &tracked);
+    for (key, entity) in destination_ids {
+        if destination.get_entity(entity).is_some() {
+            despawn_with_children_recursive(destination, entity);
+        }
+        report.despawned.push((key, entity));
+    }
+    let entities = pairs.iter().map(|(_, entity)| *entity).collect::<Vec<_>>();
+    hierarchy::propagate_transforms(destination, &entities);
+
+    if let Some(id_provider) = source.get_resource::<SnapshotIdProvider<T>>() {
+        destination.insert_resource(id_provider.clone());
+    }
+
+    for registration in type_registry.iter() {
+        let reflect_resource = match registration.data::<ReflectResource>() {
+            Some(reflect_resource) => reflect_resource,
+            None => continue,
+        };
+        let existing = reflect_resource.reflect_resource(destination).is_some();
+        if reflect_resource.reflect_resource(source).is_some() {
+            reflect_resource.copy_resource(
+                source,
+                destination,
+                registration.data::<ReflectDefault>(),
+            )?;
+            if !existing {
+                report.resources_inserted.push(registration.name());
+            }
+        } else if existing {
+            reflect_resource.remove_resource(destination);
+            report.resources_removed.push(registration.name());
+        }
+    }
+
+    for flow_type in flow.types.iter() {
+        match (flow_type.capture)(source) {
+            Some((value, _)) => {
+                if (flow_type.restore)(destination, &*value) {
+                    report.resources_inserted.push(flow_type.type_name);
+                }
+            }
+            None => {
+                if (flow_type.remove)(destination) {
+                    report.resources_removed.push(flow_type.type_name);
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
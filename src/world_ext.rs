@@ -0,0 +1,95 @@
+use bevy::{prelude::*, tasks::ComputeTaskPool};
+
+use crate::{
+    hooks,
+    world_copy::copy_world,
+    world_snapshot::{check_handle_paths, check_type_data},
+    LoadFilter, LoadMode, LoadReport, SnapError, SnapRegistry, SnapSettings, SnapType,
+    WorldSnapshot,
+};
+
+/// Fallible saving and loading directly on a `World`, e.g. from exclusive systems or tests.
+pub trait SnapWorldExt {
+    /// Takes a snapshot of all entities and resources tracked by `T`, after running the
+    /// pre-save hooks.
+    fn save_snapshot<T: SnapType>(&mut self) -> Result<WorldSnapshot<T>, SnapError>;
+
+    /// Replaces all state tracked by `T` with the contents of `snapshot`, then runs the post-load
+    /// hooks.
+    fn load_snapshot<T: SnapType>(
+        &mut self,
+        snapshot: &WorldSnapshot<T>,
+    ) -> Result<LoadReport, SnapError>;
+
+    /// Writes the parts of `snapshot` selected by `filter` to the world, according to `mode`.
+    fn load_snapshot_with<T: SnapType>(
+        &mut self,
+        snapshot: &WorldSnapshot<T>,
+        mode: LoadMode,
+        filter: &LoadFilter,
+    ) -> Result<LoadReport, SnapError>;
+
+    /// Copies all entities and resources tracked by `T` into `destination`, replacing its tracked
+    /// state, without taking a snapshot in between. Useful for prediction sub-worlds, simulation
+    /// forks and tests.
+    ///
+    /// Hooks are not run, and asset handles are copied as weak handles.
+    fn copy_tracked_to<T: SnapType>(
+        &self,
+        destination: &mut World,
+    ) -> Result<LoadReport, SnapError>;
+}
+
+impl SnapWorldExt for World {
+    fn save_snapshot<T: SnapType>(&mut self) -> Result<WorldSnapshot<T>, SnapError> {
+        let type_registry = self
+            .get_resource::<SnapRegistry<T>>()
+            .ok_or(SnapError::MissingRegistry(std::any::type_name::<T>()))?
+            .type_registry
+            .clone();
+        check_type_data(&type_registry.read())?;
+        hooks::run_pre_save::<T>(self, |_, _| true);
+        check_handle_paths::<T>(self, &type_registry.read(), |_| true)?;
+        let task_pool = self
+            .get_resource::<SnapSettings<T>>()
+            .filter(|settings| settings.parallel_capture)
+            .and_then(|_| self.get_resource::<ComputeTaskPool>());
+        let mut snapshot = match task_pool {
+            Some(task_pool) => WorldSnapshot::from_world_parallel(self, &type_registry, task_pool),
+            None => WorldSnapshot::from_world(self, &type_registry),
+        };
+        snapshot.capture_flow(self, &self.resource::<SnapRegistry<T>>().flow);
+        Ok(snapshot)
+    }
+
+    fn load_snapshot<T: SnapType>(
+        &mut self,
+        snapshot: &WorldSnapshot<T>,
+    ) -> Result<LoadReport, SnapError> {
+        self.load_snapshot_with(snapshot, LoadMode::Replace, &LoadFilter::all())
+    }
+
+    fn load_snapshot_with<T: SnapType>(
+        &mut self,
+        snapshot: &WorldSnapshot<T>,
+        mode: LoadMode,
+        filter: &LoadFilter,
+    ) -> Result<LoadReport, SnapError> {
+        let registry = self
+            .get_resource::<SnapRegistry<T>>()
+            .ok_or(SnapError::MissingRegistry(std::any::type_name::<T>()))?;
+        let type_registry = registry.type_registry.clone();
+        let flow = registry.flow.clone();
+        let mut report = snapshot.write_to_world(self, type_registry, mode, filter)?;
+        snapshot.write_flow(self, &flow, mode, filter, &mut report);
+        hooks::run_post_load(self, snapshot, &report, mode, filter);
+        Ok(report)
+    }
+
+    fn copy_tracked_to<T: SnapType>(
+        &self,
+        destination: &mut World,
+    ) -> Result<LoadReport, SnapError> {
+        copy_world::<T>(self, destination)
+    }
+}
@@ -1,45 +1,110 @@
 use bevy::{
+    ecs::{
+        archetype::Archetype,
+        component::{Component, ComponentId},
+    },
     prelude::*,
-    reflect::{Reflect, TypeRegistry},
-    utils::HashMap,
+    reflect::{Reflect, TypeRegistration, TypeRegistry, TypeRegistryInternal},
+    tasks::TaskPool,
+    utils::{HashMap, HashSet},
 };
-use std::{fmt::Debug, marker::PhantomData};
+use std::{any::TypeId, fmt::Debug, marker::PhantomData};
 
-use crate::{reflect_resource::ReflectResource, SnapType};
+use crate::{
+    flow::{FlowEntry, SnapFlow},
+    handles::{contains_handle, saved_handle, EmbeddedAsset, ReflectSnapHandle, SavedHandle},
+    hierarchy::{self, HierarchyEntry},
+    markers::{has_any, SnapMarkers},
+    reflect_resource::{ReflectDefault, ReflectResource},
+    snap_clone::{ComponentColumn, ReflectSnapClone},
+    transient::ReflectSnapTransient,
+    EntityMapping, LoadFilter, LoadReport, SnapError, SnapType, SnapshotId, SnapshotIdProvider,
+    SnapshotKey,
+};
 
-/// Add this component to all entities you want to be loaded/saved in snapshots.
-/// The `id` has to be unique. Consider using the `SnapshotIdProvider` resource.
-#[derive(Component)]
-pub struct SnapshotId<T: SnapType> {
-    id: u32,
-    t: PhantomData<T>,
+/// Maps snapshot_ids to entity id+generation. Necessary to track entities over time.
+pub(crate) fn snapshot_id_map<T: SnapType>(
+    world: &mut World,
+) -> Result<HashMap<SnapshotKey, Entity>, SnapError> {
+    let mut rid_map = HashMap::default();
+    let mut query = world.query::<(Entity, &SnapshotId<T>)>();
+    for (entity, snapshot_id) in query.iter(world) {
+        if rid_map.insert(snapshot_id.id().clone(), entity).is_some() {
+            return Err(SnapError::DuplicateId(snapshot_id.id().clone()));
+        }
+    }
+    Ok(rid_map)
 }
 
-impl<T: SnapType> SnapshotId<T> {
-    pub fn new(id: u32) -> Self {
-        Self { id, t: default() }
-    }
+/// Makes sure all registered types can be saved and loaded.
+pub(crate) fn check_type_data(type_registry: &TypeRegistryInternal) -> Result<(), SnapError> {
+    type_registry.iter().try_for_each(check_registration)
+}
 
-    pub fn id(&self) -> u32 {
-        self.id
+/// Makes sure the type can be saved and loaded, i.e. is a component or a resource.
+pub(crate) fn check_registration(registration: &TypeRegistration) -> Result<(), SnapError> {
+    if registration.data::<ReflectComponent>().is_none()
+        && registration.data::<ReflectResource>().is_none()
+    {
+        return Err(SnapError::MissingTypeData(registration.name().to_string()));
     }
+    if let Some(reflect_resource) = registration.data::<ReflectResource>() {
+        if !reflect_resource.has_constructor() && registration.data::<ReflectDefault>().is_none() {
+            return Err(SnapError::MissingConstructor(
+                registration.name().to_string(),
+            ));
+        }
+    }
+    if let Some(transient) = registration.data::<ReflectSnapTransient>() {
+        transient.check(registration.name())?;
+    }
+    Ok(())
 }
 
-/// Maps snapshot_ids to entity id+generation. Necessary to track entities over time.
-fn snapshot_id_map<T: SnapType>(world: &mut World) -> HashMap<u32, Entity> {
-    let mut rid_map = HashMap::default();
-    let mut query = world.query::<(Entity, &SnapshotId<T>)>();
-    for (entity, snapshot_id) in query.iter(world) {
-        assert!(!rid_map.contains_key(&snapshot_id.id));
-        rid_map.insert(snapshot_id.id, entity);
+/// Makes sure the handles of the tagged entities that satisfy `include` can be saved, see
+/// `ReflectSnapHandle`.
+pub(crate) fn check_handle_paths<T: SnapType>(
+    world: &World,
+    type_registry: &TypeRegistryInternal,
+    include: impl Fn(Entity) -> bool,
+) -> Result<(), SnapError> {
+    let ids = match CaptureIds::new::<T>(world, type_registry) {
+        Some(ids) => ids,
+        None => return Ok(()),
+    };
+    for registration in type_registry.iter() {
+        let (snap_handle, component_id) = match (
+            registration.data::<ReflectSnapHandle>(),
+            world.components().get_id(registration.type_id()),
+        ) {
+            (Some(snap_handle), Some(component_id)) => (snap_handle, component_id),
+            _ => continue,
+        };
+        let excluded = ids.markers.excluded(registration.type_id());
+        for archetype in ids.archetypes(world).filter(|archetype| {
+            archetype.contains(component_id)
+                && !excluded.map_or(false, |excluded| archetype.contains(excluded))
+        }) {
+            for entity in archetype
+                .entities()
+                .iter()
+                .filter(|entity| include(**entity))
+            {
+                snap_handle.check(world, *entity)?;
+            }
+        }
     }
-    rid_map
+    Ok(())
 }
 
-struct SnapshotEntity {
+pub(crate) struct SnapshotEntity {
     pub entity: Entity,
-    pub snapshot_id: u32,
+    pub snapshot_id: SnapshotKey,
     pub components: Vec<Box<dyn Reflect>>,
+    /// Snapshot id of the parent, if the parent is also tagged.
+    pub parent: Option<SnapshotKey>,
+    /// Snapshot ids of the tagged children, in order.
+    pub children: Vec<SnapshotKey>,
 }
 
 impl Clone for SnapshotEntity {
@@ -52,8 +117,10 @@ impl Clone for SnapshotEntity {
 
         Self {
             entity: self.entity,
-            snapshot_id: self.snapshot_id,
+            snapshot_id: self.snapshot_id.clone(),
             components,
+            parent: self.parent.clone(),
+            children: self.children.clone(),
         }
     }
 }
@@ -73,18 +140,48 @@ impl Debug for SnapshotEntity {
             .field("id", &self.entity.id())
             .field("generation", &self.entity.generation())
             .field("snapshot_id", &self.snapshot_id)
+            .field("parent", &self.parent)
+            .field("children", &self.children)
             .finish()
     }
 }
 
+/// How a snapshot is written to a world that already has tracked entities and resources.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Make the tracked state of the world match the snapshot exactly. Components and resources
+    /// not in the snapshot are removed, and tagged entities not in the snapshot are despawned.
+    Replace,
+    /// Update and add entities, components and resources from the snapshot, but never despawn
+    /// or remove anything.
+    Merge,
+    /// Spawn every snapshot entity as a new entity, with a fresh id from the
+    /// `SnapshotIdProvider`. Useful for prefabs and copy-paste. Resources are left untouched.
+    Additive,
+}
+
+impl Default for LoadMode {
+    fn default() -> Self {
+        LoadMode::Replace
+    }
+}
+
 /// Holds registered components of `SnapshotId` tagged entities, as well as registered resources to save and load from/to the real bevy world.
 /// The `checksum` is the sum of hash-values from all hashable objects. It is a sum for the checksum to be order insensitive. This of course
 /// is not the best checksum to ever exist, but it is a starting point.
 #[derive(Default, Debug)]
 pub struct WorldSnapshot<T: SnapType> {
-    entities: Vec<SnapshotEntity>,
+    pub(crate) entities: Vec<SnapshotEntity>,
+    /// Components stored by the typed fast path, see `ReflectSnapClone`.
+    pub(crate) columns: Vec<ComponentColumn>,
+    /// Assets of handles that have no asset path, see `register_embedded_asset`.
+    pub(crate) assets: Vec<EmbeddedAsset>,
     pub resources: Vec<Box<dyn Reflect>>,
+    /// States and events, see `SnapFlow`.
+    pub(crate) flow: Vec<FlowEntry>,
     pub checksum: u64,
+    /// State of the `SnapshotIdProvider` at the time of the snapshot.
+    id_provider: Option<SnapshotIdProvider<T>>,
     t: PhantomData<T>,
 }
 
@@ -98,62 +195,386 @@ impl<T: SnapType> Clone for WorldSnapshot<T> {
 
         Self {
             entities: self.entities.clone(),
+            columns: self.columns.clone(),
+            assets: self.assets.clone(),
             resources,
+            flow: self.flow.clone(),
             checksum: self.checksum,
+            id_provider: self.id_provider.clone(),
             t: default(),
         }
     }
 }
 
+/// Component ids needed to capture the tagged entities of an archetype.
+struct CaptureIds {
+    snapshot_id: ComponentId,
+    markers: SnapMarkers,
+}
+
+impl CaptureIds {
+    fn new<T: SnapType>(world: &World, type_registry: &TypeRegistryInternal) -> Option<Self> {
+        let components = world.components();
+        Some(Self {
+            // if there is no such component, nothing has ever been tagged
+            snapshot_id: components.get_id(TypeId::of::<SnapshotId<T>>())?,
+            markers: SnapMarkers::new(world, type_registry),
+        })
+    }
+
+    /// The archetypes with tagged entities, in the order they are captured.
+    fn archetypes<'w>(&self, world: &'w World) -> impl Iterator<Item = &'w Archetype> {
+        let snapshot_id = self.snapshot_id;
+        world
+            .archetypes()
+            .iter()
+            .filter(move |archetype| archetype.contains(snapshot_id))
+    }
+}
+
+/// The `C` of each of `entities`, which all belong to `archetype` and are in `table_rows` of its
+/// table. Components stored in tables are read straight from their column.
+pub(crate) fn column_values<'w, C: Component>(
+    world: &'w World,
+    archetype: &Archetype,
+    entities: &[Entity],
+    table_rows: &[usize],
+) -> Vec<Option<&'w C>> {
+    let component_id = match world.components().get_id(TypeId::of::<C>()) {
+        Some(component_id) if archetype.contains(component_id) => component_id,
+        _ => return vec![None; entities.len()],
+    };
+    let column = world
+        .storages()
+        .tables
+        .get(archetype.table_id())
+        .and_then(|table| table.get_column(component_id));
+    match column {
+        // SAFETY: the column holds the `C`s of the table, and `table_rows` are rows of the table.
+        // Nothing can change them while the world is borrowed.
+        Some(column) => table_rows
+            .iter()
+            .map(|row| Some(unsafe { &*column.get_data_unchecked(*row).cast::<C>() }))
+            .collect(),
+        // sparse set components
+        None => entities
+            .iter()
+            .map(|entity| world.get::<C>(*entity))
+            .collect(),
+    }
+}
+
+/// Creates a snapshot entity for each of `archetype_entities`, which all belong to `archetype`
+/// and are in `table_rows` of its table, and returns them along with the sum of their component
+/// hashes.
+fn capture_archetype<T: SnapType>(
+    world: &World,
+    archetype: &Archetype,
+    archetype_entities: &[Entity],
+    table_rows: &[usize],
+    ids: &CaptureIds,
+    type_registry: &TypeRegistryInternal,
+) -> (Vec<SnapshotEntity>, Vec<ComponentColumn>, u64) {
+    let mut entities = Vec::with_capacity(archetype_entities.len());
+    let mut columns = Vec::new();
+    let mut checksum = 0u64;
+
+    let snapshot_ids =
+        column_values::<SnapshotId<T>>(world, archetype, archetype_entities, table_rows);
+    let parents = column_values::<Parent>(world, archetype, archetype_entities, table_rows);
+    let children = column_values::<Children>(world, archetype, archetype_entities, table_rows);
+    for (i, entity) in archetype_entities.iter().enumerate() {
+        // only relationships between tagged entities are saved
+        let parent = parents[i]
+            .and_then(|parent| world.get::<SnapshotId<T>>(parent.0))
+            .map(|parent_id| parent_id.id().clone());
+        let children = children[i]
+            .map(|children| {
+                children
+                    .iter()
+                    .filter_map(|child| world.get::<SnapshotId<T>>(*child))
+                    .map(|child_id| child_id.id().clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        entities.push(SnapshotEntity {
+            entity: *entity,
+            snapshot_id: snapshot_ids[i].unwrap().id().clone(),
+            components: Vec::new(),
+            parent,
+            children,
+        });
+    }
+
+    // fill the component vectors of snapshot entities, one component column at a time
+    for component_id in archetype.components() {
+        let registration = world
+            .components()
+            .get_info(component_id)
+            .and_then(|info| type_registry.get(info.type_id().unwrap()))
+            .filter(|registration| !hierarchy::is_hierarchy_type(registration.type_id()));
+        let registration = match registration {
+            Some(registration) => registration,
+            None => continue,
+        };
+        if let Some(excluded) = ids.markers.excluded(registration.type_id()) {
+            if archetype.contains(excluded) {
+                continue;
+            }
+        }
+        if let Some(snap_handle) = registration.data::<ReflectSnapHandle>() {
+            // handles are saved by the path of their asset
+            for snapshot_entity in entities.iter_mut() {
+                if let Some(saved) =
+                    snap_handle.save(world, snapshot_entity.entity, registration.name())
+                {
+                    snapshot_entity.components.push(Box::new(saved));
+                }
+            }
+            continue;
+        }
+        let transient = registration.data::<ReflectSnapTransient>();
+        let snap_clone = registration
+            .data::<ReflectSnapClone>()
+            // cloning would include the transient fields
+            .filter(|_| transient.is_none());
+        if let Some(snap_clone) = snap_clone {
+            // typed fast path, the whole column is cloned at once
+            if !archetype_entities.is_empty() {
+                let (values, column_checksum) =
+                    snap_clone.capture(world, archetype, archetype_entities, table_rows);
+                checksum = checksum.wrapping_add(column_checksum);
+                columns.push(ComponentColumn {
+                    type_id: registration.type_id(),
+                    type_name: registration.name(),
+                    entities: (0..archetype_entities.len()).collect(),
+                    values,
+                });
+            }
+        } else if let Some(reflect_component) = registration.data::<ReflectComponent>() {
+            for snapshot_entity in entities.iter_mut() {
+                if let Some(component) =
+                    reflect_component.reflect_component(world, snapshot_entity.entity)
+                {
+                    // add the hash value of that component to the shapshot checksum, if that component supports hashing
+                    // transient fields are neither saved nor part of the checksum
+                    let hash = match transient {
+                        Some(transient) => transient.hash(component),
+                        None => component.reflect_hash(),
+                    };
+                    if let Some(hash) = hash {
+                        checksum = checksum.wrapping_add(hash);
+                    }
+                    // add the component to the shapshot
+                    let stripped = transient.map(|transient| transient.strip(component));
+                    snapshot_entity
+                        .components
+                        .push(stripped.unwrap_or_else(|| component.clone_value()));
+                }
+            }
+        }
+    }
+
+    (entities, columns, checksum)
+}
+
 impl<T: SnapType> WorldSnapshot<T> {
     pub fn from_world(world: &World, type_registry: &TypeRegistry) -> Self {
         let mut snapshot = WorldSnapshot::default();
         let type_registry = type_registry.read();
 
         // create a snapshot entity for every entity tagged with SnapshotId
-        for archetype in world.archetypes().iter() {
-            let entities_offset = snapshot.entities.len();
-            for entity in archetype.entities() {
-                if let Some(snapshot_id) = world.get::<SnapshotId<T>>(*entity) {
-                    snapshot.entities.push(SnapshotEntity {
-                        entity: *entity,
-                        snapshot_id: snapshot_id.id,
-                        components: Vec::new(),
+        if let Some(ids) = CaptureIds::new::<T>(world, &type_registry) {
+            for archetype in ids.archetypes(world) {
+                let archetype = capture_archetype::<T>(
+                    world,
+                    archetype,
+                    archetype.entities(),
+                    archetype.entity_table_rows(),
+                    &ids,
+                    &type_registry,
+                );
+                snapshot.add_archetype(archetype);
+            }
+        }
+
+        snapshot.embed_assets(world, &type_registry);
+        snapshot.capture_resources(world, &type_registry);
+        snapshot
+    }
+
+    /// Like `from_world`, but captures each archetype in a separate task on `task_pool`.
+    ///
+    /// The resulting snapshot is identical to the one `from_world` creates.
+    pub fn from_world_parallel(
+        world: &World,
+        type_registry: &TypeRegistry,
+        task_pool: &TaskPool,
+    ) -> Self {
+        let mut snapshot = WorldSnapshot::default();
+        let type_registry = type_registry.read();
+
+        if let Some(ids) = CaptureIds::new::<T>(world, &type_registry) {
+            let type_registry = &*type_registry;
+            let ids = &ids;
+            // scope returns results in the order the tasks were spawned, i.e. archetype order
+            let archetypes = task_pool.scope(|scope| {
+                for archetype in ids.archetypes(world) {
+                    // archetypes can't be shared between threads, but the world can
+                    let archetype_id = archetype.id();
+                    scope.spawn(async move {
+                        let archetype = world.archetypes().get(archetype_id).unwrap();
+                        let entities = archetype.entities();
+                        let table_rows = archetype.entity_table_rows();
+                        capture_archetype::<T>(
+                            world,
+                            archetype,
+                            entities,
+                            table_rows,
+                            ids,
+                            type_registry,
+                        )
                     });
                 }
+            });
+            for archetype in archetypes {
+                snapshot.add_archetype(archetype);
             }
+        }
 
-            // fill the component vectors of snapshot entities
-            for component_id in archetype.components() {
-                let reflect_component = world
-                    .components()
-                    .get_info(component_id)
-                    .and_then(|info| type_registry.get(info.type_id().unwrap()))
-                    .and_then(|registration| registration.data::<ReflectComponent>());
-                if let Some(reflect_component) = reflect_component {
-                    for (i, entity) in archetype
-                        .entities()
-                        .iter()
-                        .filter(|&&entity| world.get::<SnapshotId<T>>(entity).is_some())
-                        .enumerate()
-                    {
-                        if let Some(component) = reflect_component.reflect_component(world, *entity)
-                        {
-                            assert_eq!(*entity, snapshot.entities[entities_offset + i].entity);
-                            // add the hash value of that component to the shapshot checksum, if that component supports hashing
-                            if let Some(hash) = component.reflect_hash() {
-                                snapshot.checksum += hash;
-                            }
-                            // add the component to the shapshot
-                            snapshot.entities[entities_offset + i]
-                                .components
-                                .push(component.clone_value());
-                        }
-                    }
+        snapshot.embed_assets(world, &type_registry);
+        snapshot.capture_resources(world, &type_registry);
+        snapshot
+    }
+
+    /// Like `from_world`, but only captures the tagged entities that satisfy `include`, and no
+    /// resources. Hierarchy links to entities that are left out are not saved.
+    pub(crate) fn from_entities(
+        world: &World,
+        type_registry: &TypeRegistry,
+        include: impl Fn(Entity) -> bool,
+    ) -> Self {
+        let mut snapshot = WorldSnapshot::default();
+        let type_registry = type_registry.read();
+
+        if let Some(ids) = CaptureIds::new::<T>(world, &type_registry) {
+            for archetype in ids.archetypes(world) {
+                let (entities, table_rows): (Vec<_>, Vec<_>) = archetype
+                    .entities()
+                    .iter()
+                    .copied()
+                    .zip(archetype.entity_table_rows().iter().copied())
+                    .filter(|(entity, _)| include(*entity))
+                    .unzip();
+                if entities.is_empty() {
+                    continue;
                 }
+                let archetype = capture_archetype::<T>(
+                    world,
+                    archetype,
+                    &entities,
+                    &table_rows,
+                    &ids,
+                    &type_registry,
+                );
+                snapshot.add_archetype(archetype);
+            }
+        }
+
+        // links to entities that weren't captured can't be restored from this snapshot alone
+        let captured = snapshot
+            .entities
+            .iter()
+            .map(|snapshot_entity| snapshot_entity.snapshot_id.clone())
+            .collect::<HashSet<_>>();
+        for snapshot_entity in snapshot.entities.iter_mut() {
+            if !snapshot_entity
+                .parent
+                .as_ref()
+                .map_or(true, |parent| captured.contains(parent))
+            {
+                snapshot_entity.parent = None;
             }
+            snapshot_entity
+                .children
+                .retain(|child| captured.contains(child));
         }
 
+        snapshot.embed_assets(world, &type_registry);
+        snapshot
+    }
+
+    fn add_archetype(
+        &mut self,
+        (entities, columns, checksum): (Vec<SnapshotEntity>, Vec<ComponentColumn>, u64),
+    ) {
+        // column indices are relative to the archetype
+        let offset = self.entities.len();
+        self.entities.extend(entities);
+        self.columns.extend(columns.into_iter().map(|mut column| {
+            column
+                .entities
+                .iter_mut()
+                .for_each(|index| *index += offset);
+            column
+        }));
+        self.checksum = self.checksum.wrapping_add(checksum);
+    }
+
+    /// Moves the components stored by the typed fast path (see `ReflectSnapClone`) to the
+    /// reflected components of their entities, e.g. before serializing the snapshot through
+    /// reflection.
+    pub fn make_reflective(&mut self) {
+        for column in std::mem::take(&mut self.columns) {
+            for (i, index) in column.entities.iter().enumerate() {
+                self.entities[*index]
+                    .components
+                    .push(column.get(i).clone_value());
+            }
+        }
+    }
+
+    /// Embeds the assets of the handles that were saved without a path, once per asset.
+    fn embed_assets(&mut self, world: &World, type_registry: &TypeRegistryInternal) {
+        let handle_types = type_registry
+            .iter()
+            .filter_map(|registration| {
+                registration
+                    .data::<ReflectSnapHandle>()
+                    .map(|snap_handle| (registration.name(), snap_handle))
+            })
+            .collect::<HashMap<_, _>>();
+        if handle_types.is_empty() {
+            return;
+        }
+        let mut embedded_ids = HashSet::default();
+        for component in self.entities.iter().flat_map(|e| e.components.iter()) {
+            let (handle_type, snap_handle) = match handle_types.get_key_value(component.type_name())
+            {
+                Some(handle_type) => handle_type,
+                None => continue,
+            };
+            let id = match saved_handle(&**component) {
+                Some(SavedHandle::Embedded(id)) => id,
+                _ => continue,
+            };
+            if !embedded_ids.insert(id) {
+                continue;
+            }
+            if let Some(asset) = snap_handle.capture_asset(world, id) {
+                self.assets.push(EmbeddedAsset {
+                    id,
+                    handle_type,
+                    asset,
+                });
+            }
+        }
+    }
+
+    fn capture_resources(&mut self, world: &World, type_registry: &TypeRegistryInternal) {
+        // save the id provider, so ids handed out after loading don't collide with restored entities
+        self.id_provider = world.get_resource::<SnapshotIdProvider<T>>().cloned();
+
         // go through all resources and clone those that are registered
         for component_id in world.archetypes().resource().unique_components().indices() {
             let reflect_component = world
@@ -165,78 +586,571 @@ impl<T: SnapType> WorldSnapshot<T> {
                 if let Some(resource) = reflect_resource.reflect_resource(world) {
                     // add the hash value of that resource to the shapshot checksum, if that resource supports hashing
                     if let Some(hash) = resource.reflect_hash() {
-                        snapshot.checksum += hash;
+                        self.checksum = self.checksum.wrapping_add(hash);
                     }
                     // add the resource to the shapshot
-                    snapshot.resources.push(resource.clone_value());
+                    self.resources.push(resource.clone_value());
                 }
             }
         }
+    }
 
-        snapshot
+    /// Saves the states and events of `flow`.
+    pub(crate) fn capture_flow(&mut self, world: &World, flow: &SnapFlow) {
+        for flow_type in flow.types.iter() {
+            if let Some((value, hash)) = (flow_type.capture)(world) {
+                self.checksum = self.checksum.wrapping_add(hash.unwrap_or_default());
+                self.flow.push(FlowEntry {
+                    type_name: flow_type.type_name,
+                    value,
+                });
+            }
+        }
+    }
+
+    /// Restores the states and events of `flow`, like resources are restored by
+    /// `write_to_world`.
+    pub(crate) fn write_flow(
+        &self,
+        world: &mut World,
+        flow: &SnapFlow,
+        mode: LoadMode,
+        filter: &LoadFilter,
+        report: &mut LoadReport,
+    ) {
+        if mode == LoadMode::Additive {
+            return;
+        }
+        for flow_type in flow.types.iter() {
+            if !filter.includes_resource(flow_type.type_id) {
+                continue;
+            }
+            let saved = self
+                .flow
+                .iter()
+                .find(|entry| entry.type_name == flow_type.type_name);
+            match saved {
+                Some(saved) => {
+                    let inserted = (flow_type.restore)(world, &*saved.value);
+                    if inserted {
+                        report.resources_inserted.push(flow_type.type_name);
+                    }
+                }
+                None if mode == LoadMode::Replace => {
+                    let removed = (flow_type.remove)(world);
+                    if removed {
+                        report.resources_removed.push(flow_type.type_name);
+                    }
+                }
+                None => {}
+            }
+        }
     }
 
-    pub(crate) fn write_to_world(&self, world: &mut World, type_registry: TypeRegistry) {
+    pub(crate) fn write_to_world(
+        &self,
+        world: &mut World,
+        type_registry: TypeRegistry,
+        mode: LoadMode,
+        filter: &LoadFilter,
+    ) -> Result<LoadReport, SnapError> {
+        let mut report = LoadReport::default();
         let type_registry = type_registry.read();
-        let mut rid_map = snapshot_id_map::<T>(world);
-
-        // first, we write all entities
-        for snapshot_entity in self.entities.iter() {
-            // find the corresponding current entity or create new entity, if it doesn't exist
-            let entity = *rid_map
-                .entry(snapshot_entity.snapshot_id)
-                .or_insert_with(|| {
-                    world
-                        .spawn()
-                        .insert(SnapshotId::<T>::new(snapshot_entity.snapshot_id))
-                        .id()
-                });
 
-            // for each registered type, check what we need to do
-            for registration in type_registry.iter() {
-                let type_id = registration.type_id();
-                if let Some(reflect_component) = registration.data::<ReflectComponent>() {
-                    if world.entity(entity).contains_type_id(type_id) {
-                        // the entity in the world has such a component
-                        match snapshot_entity
-                            .components
-                            .iter()
-                            .find(|comp| comp.type_name() == registration.name())
-                        {
-                            // if we have data saved in the snapshot, overwrite the world
-                            Some(component) => {
-                                reflect_component.apply_component(world, entity, &**component)
-                            }
-                            // if we don't have any data saved, we need to remove that component from the entity
-                            None => reflect_component.remove_component(world, entity),
+        // check everything we can before touching the world
+        check_type_data(&type_registry)?;
+        self.validate().map_err(SnapError::InvalidSnapshot)?;
+        let mut rid_map = snapshot_id_map::<T>(world)?;
+        let mut restored_entities = Vec::with_capacity(self.entities.len());
+        let mut unregistered = HashSet::default();
+        let type_names = self
+            .entities
+            .iter()
+            .flat_map(|e| e.components.iter().map(|component| component.type_name()))
+            .chain(self.columns.iter().map(|column| column.type_name));
+        for type_name in type_names {
+            if type_registry.get_with_name(type_name).is_none() && unregistered.insert(type_name) {
+                report.warn(format!(
+                    "Snapshot contains {:?}, which is not registered, it will not be restored",
+                    type_name
+                ));
+            }
+        }
+        // only handle components are saved by asset path, handles in other components keep the
+        // ids they had when they were saved; checking one component of each type keeps this cheap
+        let mut checked_types = HashSet::default();
+        for component in self.entities.iter().flat_map(|e| e.components.iter()) {
+            let type_name = component.type_name();
+            if !checked_types.insert(type_name) {
+                continue;
+            }
+            let nested_handle =
+                type_registry
+                    .get_with_name(type_name)
+                    .map_or(false, |registration| {
+                        registration.data::<ReflectSnapHandle>().is_none()
+                            && contains_handle(&**component)
+                    });
+            if nested_handle {
+                report.warn(format!(
+                    "Snapshot contains {:?}, which holds an asset handle, the handle is restored \
+                     with the id it had when it was saved",
+                    type_name
+                ));
+            }
+        }
+
+        // resources are written last, so make sure they can be, before any entity is touched
+        if mode != LoadMode::Additive {
+            for resource in self.resources.iter() {
+                let reflect_resource = type_registry
+                    .get_with_name(resource.type_name())
+                    .filter(|registration| filter.includes_resource(registration.type_id()))
+                    .and_then(|registration| registration.data::<ReflectResource>());
+                if let Some(reflect_resource) = reflect_resource {
+                    reflect_resource.check_resource(&**resource)?;
+                }
+            }
+        }
+
+        // additive loads give every selected entity a fresh id, so take them all before spawning,
+        // and only keep the advanced provider once they are spawned
+        let mut additive_ids = Vec::new();
+        let mut additive_provider = None;
+        if mode == LoadMode::Additive {
+            let mut id_provider = world
+                .get_resource::<SnapshotIdProvider<T>>()
+                .cloned()
+                .unwrap_or_default();
+            for snapshot_entity in self.entities.iter() {
+                if filter.includes_entity(&snapshot_entity.snapshot_id) {
+                    additive_ids.push(id_provider.try_next().ok_or(SnapError::OutOfIds)?);
+                }
+            }
+            additive_provider = Some(id_provider);
+        }
+        let mut additive_ids = additive_ids.into_iter();
+
+        // handles are reloaded from their asset paths, which needs the AssetServer, or point to
+        // assets embedded in the snapshot
+        let handle_types = type_registry
+            .iter()
+            .filter(|registration| registration.data::<ReflectSnapHandle>().is_some())
+            .map(|registration| registration.name())
+            .collect::<HashSet<_>>();
+        if !handle_types.is_empty() {
+            let embedded_ids = self
+                .assets
+                .iter()
+                .map(|asset| asset.id)
+                .collect::<HashSet<_>>();
+            let saved_handles = self
+                .entities
+                .iter()
+                .filter(|snapshot_entity| filter.includes_entity(&snapshot_entity.snapshot_id))
+                .flat_map(|snapshot_entity| snapshot_entity.components.iter())
+                .filter(|component| handle_types.contains(component.type_name()));
+            for saved in saved_handles {
+                match saved_handle(&**saved) {
+                    Some(SavedHandle::Path(_)) => {
+                        if world.get_resource::<AssetServer>().is_none() {
+                            return Err(SnapError::MissingResource("AssetServer".to_string()));
                         }
+                    }
+                    Some(SavedHandle::Embedded(id)) if embedded_ids.contains(&id) => {}
+                    _ => {
+                        return Err(SnapError::TypeMismatch {
+                            expected: "a handle saved by asset path or embedded asset".to_string(),
+                            found: saved.type_name().to_string(),
+                        })
+                    }
+                }
+            }
+        }
+
+        // add each embedded asset once, no matter how many handles point to it
+        let mut embedded_handles = HashMap::default();
+        for embedded in self.assets.iter() {
+            let added = type_registry
+                .get_with_name(embedded.handle_type)
+                .and_then(|registration| registration.data::<ReflectSnapHandle>())
+                .and_then(|snap_handle| snap_handle.add_asset(world, &*embedded.asset));
+            if let Some(handle) = added {
+                embedded_handles.insert(embedded.id, handle?);
+            }
+        }
+
+        // first, we find the entities to write to, and spawn the missing ones in one batch
+        let selected = self
+            .entities
+            .iter()
+            .enumerate()
+            .filter(|(_, snapshot_entity)| filter.includes_entity(&snapshot_entity.snapshot_id))
+            .collect::<Vec<_>>();
+        let mut existing = Vec::with_capacity(selected.len());
+        let mut new_ids = Vec::new();
+        for (_, snapshot_entity) in selected.iter() {
+            let entity = if mode == LoadMode::Additive {
+                // always create a new entity, with a new id
+                None
+            } else {
+                // find the corresponding current entity, if it exists
+                rid_map.get(&snapshot_entity.snapshot_id).copied()
+            };
+            match entity {
+                Some(_) => report.updated.push(snapshot_entity.snapshot_id.clone()),
+                None => {
+                    report.spawned.push(snapshot_entity.snapshot_id.clone());
+                    new_ids.push(if mode == LoadMode::Additive {
+                        additive_ids.next().unwrap()
                     } else {
-                        // the entity in the world has no such component
-                        if let Some(component) = snapshot_entity
-                            .components
-                            .iter()
-                            .find(|comp| comp.type_name() == registration.name())
-                        {
-                            // if we have data saved in the snapshot, add the component to the entity
-                            reflect_component.add_component(world, entity, &**component);
+                        SnapshotId::<T>::from_key(snapshot_entity.snapshot_id.clone())
+                    });
+                }
+            }
+            existing.push(entity);
+        }
+        let mut spawned = world
+            .spawn_batch(new_ids.into_iter().map(|snapshot_id| (snapshot_id,)))
+            .collect::<Vec<_>>()
+            .into_iter();
+        if let Some(id_provider) = additive_provider {
+            world.insert_resource(id_provider);
+        }
+
+        // restored index and entity of each snapshot entity, if it was selected
+        let mut restored_indices = vec![None; self.entities.len()];
+        let mut targets = vec![None; self.entities.len()];
+        for ((index, snapshot_entity), entity) in selected.into_iter().zip(existing.iter()) {
+            let entity = entity.unwrap_or_else(|| spawned.next().unwrap());
+            restored_indices[index] = Some(restored_entities.len());
+            targets[index] = Some(entity);
+            if mode != LoadMode::Additive {
+                rid_map.insert(snapshot_entity.snapshot_id.clone(), entity);
+            }
+            report.entities.insert(
+                snapshot_entity.snapshot_id.clone(),
+                EntityMapping {
+                    old: snapshot_entity.entity,
+                    new: entity,
+                },
+            );
+
+            restored_entities.push((snapshot_entity, entity));
+        }
+        let snapshot_entities = restored_entities
+            .iter()
+            .map(|(_, entity)| *entity)
+            .collect::<Vec<_>>();
+
+        // returns how to write `component`, if it should be written at all
+        let component_registration = |component: &dyn Reflect| {
+            type_registry
+                .get_with_name(component.type_name())
+                .filter(|registration| {
+                    // parent-child relationships are restored separately below
+                    !hierarchy::is_hierarchy_type(registration.type_id())
+                        && filter.includes_component(registration.type_id())
+                })
+                .and_then(|registration| {
+                    registration
+                        .data::<ReflectComponent>()
+                        .map(|reflect_component| (registration, reflect_component))
+                })
+        };
+
+        // new entities saved with the same components end up in the same archetype, so group them
+        // and add each type's components to a whole group at a time, which moves the group along
+        // the same archetype edges and looks each registration up once per group
+        let mut groups = Vec::<Vec<usize>>::new();
+        let mut group_indices = HashMap::<Vec<&str>, usize>::default();
+        for (index, (snapshot_entity, _)) in restored_entities
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| existing[*index].is_none())
+        {
+            let type_names = snapshot_entity
+                .components
+                .iter()
+                .map(|component| component.type_name())
+                .collect::<Vec<_>>();
+            let group = *group_indices.entry(type_names).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[group].push(index);
+        }
+        for group in groups.iter() {
+            let (first, _) = restored_entities[group[0]];
+            for (position, component) in first.components.iter().enumerate() {
+                let (registration, reflect_component) = match component_registration(&**component) {
+                    Some(found) => found,
+                    None => continue,
+                };
+                let snap_handle = registration.data::<ReflectSnapHandle>();
+                for index in group {
+                    let (snapshot_entity, entity) = restored_entities[*index];
+                    let component = &*snapshot_entity.components[position];
+                    match snap_handle {
+                        Some(snap_handle) => {
+                            snap_handle.load(world, entity, component, &embedded_handles)
                         }
-                        // if both the snapshot and the world does not have the registered component, we don't need to to anything
+                        None => reflect_component.add_component(world, entity, component),
+                    }
+                    report.components_added.push((entity, registration.name()));
+                }
+            }
+        }
+
+        // group the components of existing entities by type, so each registration is only looked
+        // up once
+        let mut components_by_type = HashMap::<_, Vec<(usize, &dyn Reflect)>>::default();
+        for (index, (snapshot_entity, _)) in restored_entities
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| existing[*index].is_some())
+        {
+            for component in snapshot_entity.components.iter() {
+                if let Some((registration, _)) = component_registration(&**component) {
+                    components_by_type
+                        .entry(registration.type_id())
+                        .or_default()
+                        .push((index, &**component));
+                }
+            }
+        }
+
+        let mut columns_by_type = HashMap::<_, Vec<&ComponentColumn>>::default();
+        for column in self.columns.iter() {
+            if let Some(registration) = type_registry.get_with_name(column.type_name) {
+                columns_by_type
+                    .entry(registration.type_id())
+                    .or_default()
+                    .push(column);
+            }
+        }
+
+        // then, for each registered type, write its components to the restored entities
+        let markers = SnapMarkers::new(world, &type_registry);
+        let mut in_snapshot = vec![false; snapshot_entities.len()];
+        // restored index of each existing entity, by entity id, to find the ones that may lose a
+        // component
+        let mut existing_indices = Vec::new();
+        for (index, entity) in snapshot_entities.iter().copied().enumerate() {
+            if existing[index].is_some() {
+                let slot = entity.id() as usize;
+                if existing_indices.len() <= slot {
+                    existing_indices.resize(slot + 1, None);
+                }
+                existing_indices[slot] = Some(index);
+            }
+        }
+        for registration in type_registry.iter() {
+            let type_id = registration.type_id();
+            if hierarchy::is_hierarchy_type(type_id) {
+                // parent-child relationships are restored separately below
+                continue;
+            }
+            if !filter.includes_component(type_id) {
+                continue;
+            }
+            let reflect_component = match registration.data::<ReflectComponent>() {
+                Some(reflect_component) => reflect_component,
+                None => continue,
+            };
+            let components = components_by_type.remove(&type_id).unwrap_or_default();
+            let columns = columns_by_type.remove(&type_id).unwrap_or_default();
+            let component_id = world.components().get_id(type_id);
+            // only existing entities that have the component can lose it, so look them up by
+            // archetype instead of checking every restored entity
+            let mut holders = match (component_id, mode) {
+                (Some(component_id), LoadMode::Replace) => world
+                    .archetypes()
+                    .iter()
+                    .filter(|archetype| archetype.contains(component_id))
+                    .flat_map(|archetype| archetype.entities().iter())
+                    .filter_map(|entity| existing_indices.get(entity.id() as usize).copied())
+                    .flatten()
+                    .collect::<Vec<_>>(),
+                _ => Vec::new(),
+            };
+            if components.is_empty() && columns.is_empty() && holders.is_empty() {
+                continue;
+            }
+            holders.sort_unstable();
+            let transient_defaults = registration
+                .data::<ReflectSnapTransient>()
+                .and_then(|transient| transient.defaults());
+            let snap_handle = registration.data::<ReflectSnapHandle>();
+            // entities with SnapExclude or SnapPinned markers for this type keep their component
+            let protected = markers.protected(type_id);
+            let unprotected_targets;
+            let targets = if protected.is_empty() {
+                &targets
+            } else {
+                unprotected_targets = targets
+                    .iter()
+                    .map(|entity| entity.filter(|entity| !has_any(world, *entity, &protected)))
+                    .collect::<Vec<_>>();
+                &unprotected_targets
+            };
+
+            for (index, component) in components.iter() {
+                let entity = snapshot_entities[*index];
+                in_snapshot[*index] = true;
+                if has_any(world, entity, &protected) {
+                    continue;
+                }
+                if let Some(snap_handle) = snap_handle {
+                    // applying would only change the id, not which asset the handle keeps loaded
+                    if !component_id.map_or(false, |id| world.entity(entity).contains_id(id)) {
+                        report.components_added.push((entity, registration.name()));
+                    }
+                    snap_handle.load(world, entity, *component, &embedded_handles);
+                } else if component_id.map_or(false, |id| world.entity(entity).contains_id(id)) {
+                    // if we have data saved in the snapshot, overwrite the world
+                    reflect_component.apply_component(world, entity, *component);
+                    if let Some(defaults) = &transient_defaults {
+                        reflect_component.apply_component(world, entity, defaults);
                     }
                 } else {
-                    error!(
-                        "Unrecognized type in snapshot type registry: {:?}. Did you forget to add #[reflect(Component)] to it?",
-                        registration.name()
-                    );
+                    // if the entity has no such component yet, add it
+                    reflect_component.add_component(world, entity, *component);
+                    report.components_added.push((entity, registration.name()));
                 }
             }
 
-            // afterwards, remove the pair from the map (leftover entities will need to be despawned)
-            rid_map.remove(&snapshot_entity.snapshot_id);
+            for column in columns {
+                match registration.data::<ReflectSnapClone>() {
+                    Some(snap_clone) => {
+                        let mut added = Vec::new();
+                        snap_clone.write(world, column, targets, &mut added);
+                        report.components_added.extend(
+                            added
+                                .into_iter()
+                                .map(|entity| (entity, registration.name())),
+                        );
+                    }
+                    // the type was registered without the fast path since the snapshot was taken
+                    None => {
+                        for (i, index) in column.entities.iter().enumerate() {
+                            let entity = match targets[*index] {
+                                Some(entity) => entity,
+                                None => continue,
+                            };
+                            if world.entity(entity).contains_type_id(type_id) {
+                                reflect_component.apply_component(world, entity, column.get(i));
+                            } else {
+                                reflect_component.add_component(world, entity, column.get(i));
+                                report.components_added.push((entity, registration.name()));
+                            }
+                        }
+                    }
+                }
+                for index in column
+                    .entities
+                    .iter()
+                    .filter_map(|index| restored_indices[*index])
+                {
+                    in_snapshot[index] = true;
+                }
+            }
+
+            // if we don't have any data saved, we need to remove that component from the entity
+            for index in holders {
+                let entity = snapshot_entities[index];
+                if !in_snapshot[index] && !has_any(world, entity, &protected) {
+                    reflect_component.remove_component(world, entity);
+                    report
+                        .components_removed
+                        .push((entity, registration.name()));
+                }
+            }
+            in_snapshot.fill(false);
         }
 
-        // despawn entities which have a snapshot id component but where not present in the snapshot
-        for (_, v) in rid_map.iter() {
-            world.despawn(*v);
+        // restore parent-child relationships between tagged entities
+        let tracked = rid_map
+            .values()
+            .chain(snapshot_entities.iter())
+            .copied()
+            .collect::<HashSet<_>>();
+        if filter.includes_all_components() {
+            let entity_map = restored_entities
+                .iter()
+                .map(|(snapshot_entity, entity)| (&snapshot_entity.snapshot_id, *entity))
+                .collect::<HashMap<_, _>>();
+            let selected = rid_map
+                .iter()
+                .filter(|(id, _)| filter.includes_entity(id))
+                .map(|(_, entity)| *entity)
+                .chain(snapshot_entities.iter().copied())
+                .collect::<HashSet<_>>();
+            let hierarchy_entries = restored_entities
+                .iter()
+                .map(|(snapshot_entity, entity)| HierarchyEntry {
+                    entity: *entity,
+                    parent: snapshot_entity
+                        .parent
+                        .as_ref()
+                        .and_then(|id| entity_map.get(id).copied()),
+                    children: snapshot_entity
+                        .children
+                        .iter()
+                        .filter_map(|id| entity_map.get(id).copied())
+                        .collect(),
+                })
+                .collect::<Vec<_>>();
+            hierarchy::restore_hierarchy(world, &hierarchy_entries, &tracked, &selected);
+        }
+
+        if mode == LoadMode::Replace {
+            // afterwards, remove the restored entities from the map (leftover entities will need to be despawned)
+            for snapshot_entity in self.entities.iter() {
+                rid_map.remove(&snapshot_entity.snapshot_id);
+            }
+
+            // despawn entities which have a snapshot id component but where not present in the snapshot,
+            // along with their untracked children
+            let despawned = rid_map
+                .into_iter()
+                .filter(|(id, _)| filter.includes_entity(id))
+                .collect::<Vec<_>>();
+            // tracked children that stay are detached instead
+            let mut kept = tracked;
+            for (_, entity) in despawned.iter() {
+                kept.remove(entity);
+            }
+            let entities = despawned
+                .iter()
+                .map(|(_, entity)| *entity)
+                .collect::<Vec<_>>();
+            hierarchy::despawn_batch(world, &entities, &kept);
+            report.despawned.extend(despawned);
+        }
+
+        // make sure global transforms reflect the restored hierarchy
+        hierarchy::propagate_transforms(world, &snapshot_entities);
+
+        if mode == LoadMode::Additive {
+            // new entities got new ids, and resources are left untouched
+            return Ok(report);
+        }
+
+        // restore the id provider, and make sure it never hands out ids of restored entities
+        if mode == LoadMode::Replace && filter.includes_everything() {
+            if let Some(id_provider) = &self.id_provider {
+                world.insert_resource(id_provider.clone());
+            }
+        }
+        if let Some(mut id_provider) = world.get_resource_mut::<SnapshotIdProvider<T>>() {
+            for index in self
+                .entities
+                .iter()
+                .filter_map(|snapshot_entity| snapshot_entity.snapshot_id.index())
+            {
+                id_provider.reserve(index);
+            }
         }
 
         // then, we write all resources
@@ -245,6 +1159,9 @@ impl<T: SnapType> WorldSnapshot<T> {
                 Some(res) => res,
                 None => continue, // likely this is a non-resource component, skip it.
             };
+            if !filter.includes_resource(registration.type_id()) {
+                continue;
+            }
 
             match reflect_resource.reflect_resource(world) {
                 // the world has such a resource
@@ -257,10 +1174,14 @@ impl<T: SnapType> WorldSnapshot<T> {
                     {
                         // if both the world and the snapshot has the resource, apply the values
                         Some(snapshot_res) => {
-                            reflect_resource.apply_resource(world, &**snapshot_res);
+                            reflect_resource.apply_resource(world, &**snapshot_res)?;
                         }
                         // if only the world has the resource, but it doesn't exist in the snapshot, remove the resource
-                        None => reflect_resource.remove_resource(world),
+                        None if mode == LoadMode::Replace => {
+                            reflect_resource.remove_resource(world);
+                            report.resources_removed.push(registration.name());
+                        }
+                        None => {}
                     }
                 }
                 // the world does not have this resource
@@ -271,11 +1192,18 @@ impl<T: SnapType> WorldSnapshot<T> {
                         .iter()
                         .find(|res| res.type_name() == registration.name())
                     {
-                        reflect_resource.add_resource(world, &**snapshot_res);
+                        reflect_resource.add_resource(
+                            world,
+                            &**snapshot_res,
+                            registration.data::<ReflectDefault>(),
+                        )?;
+                        report.resources_inserted.push(registration.name());
                     }
                     // if both the world and the snapshot does not have this resource, do nothing
                 }
             }
         }
+
+        Ok(report)
     }
 }